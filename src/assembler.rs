@@ -1,51 +1,191 @@
+pub mod directive;
+pub mod expr;
 pub mod instruction;
+pub mod layout;
 pub mod parser;
+pub mod pseudo;
+pub mod register;
+pub mod reloc;
+pub mod section;
 
+use self::directive::Directive;
+use self::expr::{Expr, SymbolTable};
 use self::instruction::Instruction;
+use self::layout::{Item, Statement};
+use self::reloc::Relocation;
+use self::section::{Label, Section};
+use crate::utils::diagnostic::Diagnostic;
+use crate::utils::exception::AsmRiscVError;
 
-/// Turn each instruction type into little endian bytes
-pub fn assembly(instructions: &Vec<Instruction>) -> Vec<u8>{
-    let mut binary_contents = Vec::new();
-    
-    for ins in instructions {
-        match ins {
-            // I-type: imm[11:0] | rs1[4:0] | funct3[2:0] | rd[4:0] | opcode[6:0]
-            Instruction::Itype {rd, rs1, imm, opcode, funct3} => {
-                let ins_u32 = ((imm << 20) as u32) | (rs1 << 15) | (funct3 << 12) | (rd << 7) | opcode;
-                binary_contents.extend_from_slice(&ins_u32.to_le_bytes());
-            },
+use std::collections::{HashMap, HashSet};
 
-            // R-type: funct[6:0] | rs2[4:0] | rs1[4:0] | funct3[2:0] | rd[4:0] | opcode[6:0]
-            Instruction::Rtype {rd, rs1, rs2, opcode, funct3, funct7} => {
-                let ins_u32 = (funct7 << 25) | (rs2 << 20) | (rs1 << 15) | (funct3 << 12) | (rd << 7) | opcode;
-                binary_contents.extend_from_slice(&ins_u32.to_le_bytes());
-            }
+/// Where the bytes of one source statement were placed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LineInfo {
+    /// 1-based line in the source file
+    pub line: usize,
+    pub location: Label,
+    pub size: u32,
+    /// Whether the bytes are instructions rather than data
+    pub instruction: bool,
+}
 
-            // S-type: imm[11:5] | rs2[4:0] | rs1[4:0] | funct3[2:0] | imm[4:0] | opcode[6:0]
-            Instruction::Stype {rs1, rs2, imm, opcode, funct3} => {
-                let ins_u32 = (((imm & 0xfe0) << 20) as u32) | (rs2 << 20) | (rs1 << 15) | (funct3 << 12) | (((imm & 0x1f) << 7) as u32) | opcode;
-                binary_contents.extend_from_slice(&ins_u32.to_le_bytes());
-            },
+/// Result of assembling one source file
+#[derive(Debug, Default)]
+pub struct Object {
+    /// Contents of each section, indexed by `Section::index`. `.bss` only ever holds zeros.
+    pub sections: [Vec<u8>; 4],
+    /// Boundary each section must start on
+    pub alignments: [u32; 4],
+    pub symbols: SymbolTable,
+    pub globals: HashSet<String>,
+    /// Fields left empty until section addresses are known
+    pub relocations: Vec<Relocation>,
+    /// Every statement in source order
+    pub lines: Vec<LineInfo>,
+    /// Line each label is defined on
+    pub label_lines: HashMap<String, usize>,
+}
 
-            // B-type: imm[12] | imm[10:5] | rs2[4:0] | rs1[4:0] | funct3[2:0] | imm[4:1] | imm[11] | opcode[6:0]
-            Instruction::Btype {rs1, rs2, imm, opcode, funct3} => {
-                let ins_u32 = ((((imm & 0x1000) << 19) | ((imm & 0x07e0) << 20)) as u32) | (rs2 << 20) | (rs1 << 15) | (funct3 << 12) | ((((imm & 0x01e) << 7) | ((imm & 0x800) >> 4)) as u32) | opcode;
-                binary_contents.extend_from_slice(&ins_u32.to_le_bytes());
-            },
+impl Object {
+    pub fn section(&self, section: Section) -> &Vec<u8> {
+        &self.sections[section.index()]
+    }
 
-            // U-type: imm[31:12] | rd[4:0] | opcode[6:0]
-            Instruction::Utype {rd, imm, opcode} => {
-                let ins_u32 = (((imm & 0x000fffff) << 12) as u32) | (rd << 7) | opcode;
-                binary_contents.extend_from_slice(&ins_u32.to_le_bytes());
-            }
+    /// Instructions need at least a word boundary, so every section gets one
+    pub fn alignment(&self, section: Section) -> u32 {
+        self.alignments[section.index()].max(4)
+    }
+
+    /// Source line of the statement whose bytes include `location`
+    pub fn line_at(&self, location: Label) -> Option<usize> {
+        self.lines.iter()
+                  .find(|info| info.location.section == location.section
+                               && (info.location.offset..info.location.offset + info.size).contains(&location.offset))
+                  .map(|info| info.line)
+    }
+}
 
-            // J-type: imm[20] | imm[10:1] | imm[11] | imm[19:12] | rd[4:0] | opcode[6:0]
-            Instruction::Jtype {rd, imm, opcode} => {
-                let ins_u32 = ((((imm & 0x100000) << 11) | ((imm & 0x0007fe) << 20) | ((imm & 0x000800) << 9) | (imm & 0x0ff000)) as u32) | (rd << 7) | opcode;
-                binary_contents.extend_from_slice(&ins_u32.to_le_bytes());
+/// Assemble a whole source file, collecting every error and warning instead of stopping at the first.
+/// The layout pass places every statement and label at a byte offset, the second pass emits the section contents.
+/// Constants from `.equ`/`.set` are defined in source order during both passes.
+/// A statement with an error is filled with zeros so the ones after it keep their place,
+/// except that encoding stops at the first statement too large to hold in memory.
+pub fn assemble(source: &str) -> (Object, Vec<Diagnostic>) {
+    let lines = parser::split_statements(source);
+    let mut object = Object::default();
+    let mut diagnostics = Vec::new();
+    let layout = layout::layout(&lines, &mut object.symbols, &mut diagnostics);
+    object.alignments = layout.alignments;
+    object.label_lines = layout.labels;
+
+    // Only the first of a run of misaligned statements is warned about
+    let mut misaligned = [false; 4];
+    object.symbols.constants.clear();
+    for item in &layout.items {
+        let section = item.location.section;
+        let instruction = matches!(item.statement, Statement::Instruction);
+        object.lines.push(LineInfo { line: item.line_number, location: item.location, size: item.size, instruction });
+
+        let mut bytes = match encode_item(item, &mut object) {
+            Ok(Some(bytes)) => bytes,
+            Ok(None) => continue,
+            // Zero filling this statement would fail the same way, so nothing after it can be placed
+            Err(e) if matches!(e.error, AsmRiscVError::OutOfMemory(_)) => {
+                diagnostics.push(e);
+                break;
+            },
+            Err(e) => {
+                diagnostics.push(e);
+                vec![0; item.size as usize]
             }
+        };
+        let warning = misalignment(item);
+        let previous = std::mem::replace(&mut misaligned[section.index()], warning.is_some());
+        if let Some(warning) = warning.filter(|_| !previous) {
+            diagnostics.push(warning);
+        }
+
+        // Every label was placed using the size from the layout pass
+        if bytes.len() as u32 != item.size || object.section(section).len() as u32 != item.location.offset {
+            diagnostics.push(item.diagnostic(AsmRiscVError::Internal("size changed between the layout and encoding passes")));
+            bytes.resize(item.size as usize, 0);
+        }
+        if object.sections[section.index()].try_reserve(bytes.len()).is_err() {
+            diagnostics.push(item.diagnostic(AsmRiscVError::OutOfMemory(item.size)));
+            break;
         }
+        object.sections[section.index()].extend(bytes);
+    }
+
+    diagnostics.sort_by_key(|diagnostic| diagnostic.line);
+    (object, diagnostics)
+}
+
+/// Bytes of one statement, or `None` for statements that place nothing
+fn encode_item(item: &Item, object: &mut Object) -> Result<Option<Vec<u8>>, Diagnostic> {
+    let section = item.location.section;
+    match &item.statement {
+        Statement::Directive(Directive::Globl(names)) => {
+            object.globals.extend(names.iter().cloned());
+            Ok(None)
+        },
+        Statement::Directive(Directive::Equ { name, value, redefinable }) => {
+            define_constant(&mut object.symbols, name.clone(), value, *redefinable).map_err(|e| item.diagnostic(e))?;
+            Ok(None)
+        },
+        Statement::Directive(directive) => {
+            let bytes = directive.bytes(&object.symbols, item.location, &mut object.relocations).map_err(|e| item.diagnostic(e))?;
+            if section == Section::Bss && bytes.iter().any(|byte| *byte != 0) {
+                return Err(item.diagnostic(AsmRiscVError::DataInBss));
+            }
+            Ok(Some(bytes))
+        },
+        Statement::Instruction => {
+            if section == Section::Bss {
+                return Err(item.diagnostic(AsmRiscVError::InstructionInBss));
+            }
+            let expansion = parser::parse_instruction(item.line, &object.symbols, item.location, &mut object.relocations)
+                                   .map_err(|e| item.diagnostic(e))?;
+            Ok(Some(assembly(&expansion)))
+        },
+        Statement::Empty => Ok(None)
+    }
+}
+
+/// Warn about instructions and multi-byte data that do not start on their natural boundary,
+/// which cores without misaligned access support fault on
+fn misalignment(item: &Item) -> Option<Diagnostic> {
+    let (what, alignment) = match item.statement {
+        Statement::Instruction => ("instruction", 4),
+        Statement::Directive(Directive::Data { width: 2, .. }) => ("`.half` value", 2),
+        Statement::Directive(Directive::Data { width: 4, .. }) => ("`.word` value", 4),
+        Statement::Directive(Directive::Data { width: 8, .. }) => ("`.dword` value", 4),
+        _ => return None
+    };
+
+    let Label { section, offset } = item.location;
+    (offset % alignment != 0).then(|| {
+        item.diagnostic(AsmRiscVError::Misaligned { what, section: section.name(), offset, alignment }).warning()
+    })
+}
+
+fn define_constant(table: &mut SymbolTable, name: String, value: &Expr, redefinable: bool) -> Result<(), AsmRiscVError> {
+    let value = value.eval(table)?.absolute()?;
+    if table.labels.contains_key(&name) || (!redefinable && table.constants.contains_key(&name)) {
+        return Err(AsmRiscVError::DuplicateSymbol(name));
+    }
+    table.constants.insert(name, value);
+    Ok(())
+}
+
+/// Turn each instruction type into little endian bytes
+pub fn assembly(instructions: &Vec<Instruction>) -> Vec<u8>{
+    let mut binary_contents = Vec::new();
+    
+    for ins in instructions {
+        binary_contents.extend_from_slice(&ins.encode().to_le_bytes());
     }
 
     binary_contents
-}
\ No newline at end of file
+}
@@ -0,0 +1,375 @@
+use super::expr::{Expr, SymbolTable};
+use super::parser::split_operands;
+use super::reloc::{RelocKind, Relocation, Target};
+use super::section::{Label, Section};
+use crate::utils::exception::AsmRiscVError;
+
+/// `addi x0, x0, 0`
+const NOP: u32 = 0x00000013;
+
+#[derive(Debug)]
+pub enum Directive {
+    /// `.text`, `.data`, `.rodata`, `.bss`, `.section`
+    Section(Section),
+    /// `.byte`, `.half`, `.word`, `.dword`: little endian values of `width` bytes each
+    Data { width: usize, values: Vec<Expr> },
+    /// `.ascii`, `.asciz`, `.string`: already escaped and terminated
+    Ascii(Vec<u8>),
+    /// `.space`, `.zero`
+    Space { size: Expr, fill: Expr },
+    /// `.org`: pad up to `offset`, which is relative to the start of the current section
+    Org { offset: Expr, fill: Expr },
+    /// `.align`, `.p2align`, `.balign`: `amount` is a power of two exponent unless `bytes` is set.
+    /// Code is padded with `nop` when no fill is given. Nothing is padded if more than `max` bytes are needed.
+    Align { amount: Expr, bytes: bool, fill: Option<Expr>, max: Option<Expr> },
+    /// `.fill`: `repeat` copies of the low `size` bytes of `value`
+    Fill { repeat: Expr, size: Expr, value: Expr },
+    /// `.globl`, `.global`
+    Globl(Vec<String>),
+    /// `.equ`, `.set`: only `.set` may redefine a name
+    Equ { name: String, value: Expr, redefinable: bool },
+}
+
+impl Directive {
+    /// Number of bytes the directive places in the current section starting at `location`
+    pub fn size(&self, table: &SymbolTable, location: Label) -> Result<u32, AsmRiscVError> {
+        match self {
+            Directive::Data { width, values } => Ok((width * values.len()) as u32),
+            Directive::Ascii(bytes) => Ok(bytes.len() as u32),
+            Directive::Space { size, .. } => {
+                to_size(size.eval(table)?.absolute()?, "`.space` size")
+            },
+            Directive::Org { offset, .. } => {
+                let target = offset.eval(table)?;
+                if let Some(section) = target.section && section != location.section {
+                    return Err(AsmRiscVError::OrgSection { expected: location.section.name(), found: section.name() });
+                }
+                u32::try_from(target.offset - location.offset as i64)
+                    .map_err(|_| AsmRiscVError::OrgBackwards { from: location.offset, to: target.offset })
+            },
+            Directive::Align { max, .. } => {
+                let alignment = self.alignment(table)?;
+                let padding = location.offset.next_multiple_of(alignment) - location.offset;
+                match max {
+                    Some(max) if padding as i64 > max.eval(table)?.absolute()? => Ok(0),
+                    _ => Ok(padding)
+                }
+            },
+            Directive::Fill { repeat, size, .. } => {
+                let repeat = to_size(repeat.eval(table)?.absolute()?, "`.fill` repeat count")?;
+                repeat.checked_mul(fill_size(size, table)?).ok_or(AsmRiscVError::AddressOverflow)
+            },
+            Directive::Section(_) | Directive::Globl(_) | Directive::Equ { .. } => Ok(0),
+        }
+    }
+
+    /// Byte boundary requested by an alignment directive, 1 for any other directive
+    pub fn alignment(&self, table: &SymbolTable) -> Result<u32, AsmRiscVError> {
+        let Directive::Align { amount, bytes, .. } = self else {
+            return Ok(1);
+        };
+
+        let amount = amount.eval(table)?.absolute()?;
+        match bytes {
+            true if amount == 0 => Ok(1),
+            true if amount > 0 && amount <= 1 << 30 && (amount as u32).is_power_of_two() => Ok(amount as u32),
+            true if amount > 0 && amount <= 1 << 30 => Err(AsmRiscVError::NotPowerOfTwo(amount)),
+            true => Err(AsmRiscVError::ValueOutOfRange { value: amount, min: 0, max: 1 << 30, context: "alignment" }),
+            false if (0..=30).contains(&amount) => Ok(1 << amount),
+            false => Err(AsmRiscVError::ValueOutOfRange { value: amount, min: 0, max: 30, context: "alignment exponent" })
+        }
+    }
+
+    /// Bytes the directive places in the current section starting at `location`.
+    /// `.word` values that are addresses are left zero and recorded in `relocations`.
+    pub fn bytes(&self, table: &SymbolTable, location: Label, relocations: &mut Vec<Relocation>) -> Result<Vec<u8>, AsmRiscVError> {
+        match self {
+            Directive::Data { width, values } => {
+                let mut bytes = Vec::with_capacity(width * values.len());
+                for value in values {
+                    let value = match value.eval_target(table)? {
+                        (Target::Absolute, _) => eval_data_value(value, *width, table)?,
+                        (target, addend) if *width == 4 => {
+                            let location = Label { offset: location.offset + bytes.len() as u32, ..location };
+                            relocations.push(Relocation { location, kind: RelocKind::Abs32, target, addend });
+                            0
+                        },
+                        _ => return Err(AsmRiscVError::AddressWidth(*width))
+                    };
+                    bytes.extend(value.to_le_bytes().into_iter().take(*width));
+                }
+                Ok(bytes)
+            },
+            Directive::Ascii(bytes) => Ok(bytes.clone()),
+            Directive::Space { fill, .. } | Directive::Org { fill, .. } | Directive::Align { fill: Some(fill), .. } => {
+                let fill = eval_data_value(fill, 1, table)? as u8;
+                filled(fill, self.size(table, location)?)
+            },
+            Directive::Align { fill: None, .. } => {
+                let mut bytes = filled(0, self.size(table, location)?)?;
+                if location.section == Section::Text {
+                    // Whole instruction slots get `nop`; a misaligned start is zero padded
+                    let start = ((location.offset.next_multiple_of(4) - location.offset) as usize).min(bytes.len());
+                    for slot in bytes[start..].chunks_exact_mut(4) {
+                        slot.copy_from_slice(&NOP.to_le_bytes());
+                    }
+                }
+                Ok(bytes)
+            },
+            Directive::Fill { size, value, .. } => {
+                let mut bytes = filled(0, self.size(table, location)?)?;
+                let size = fill_size(size, table)? as usize;
+                // Like GNU as, the value is 4 bytes wide and any further bytes are zero
+                let value = (value.eval(table)?.absolute()? as u32 as u64).to_le_bytes();
+                if size > 0 {
+                    for copy in bytes.chunks_exact_mut(size) {
+                        copy.copy_from_slice(&value[..size]);
+                    }
+                }
+                Ok(bytes)
+            },
+            Directive::Section(_) | Directive::Globl(_) | Directive::Equ { .. } => Ok(Vec::new()),
+        }
+    }
+}
+
+/// Parse a line whose operation starts with `.`.
+/// `op_str` is the directive name and `args_str` the raw operands.
+pub fn parse_directive(op_str: &str, args_str: &str) -> Result<Directive, AsmRiscVError> {
+    let args = split_operands(args_str);
+
+    match op_str {
+        ".text" | ".data" | ".rodata" | ".bss" => {
+            if !args.is_empty() {
+                return Err(operand_count(op_str, ""));
+            }
+            Ok(Directive::Section(Section::from_name(op_str)?))
+        },
+
+        ".section" => {
+            match args.first() {
+                Some(name) => Ok(Directive::Section(Section::from_name(name)?)),
+                None => Err(operand_count(op_str, "name[, flags...]"))
+            }
+        },
+
+        ".byte" | ".half" | ".word" | ".dword" => {
+            let width = match op_str {
+                ".byte" => 1,
+                ".half" => 2,
+                ".word" => 4,
+                ".dword" => 8,
+                _ => return Err(AsmRiscVError::UnknownDirective(op_str.to_string()))
+            };
+
+            if args.is_empty() {
+                return Err(operand_count(op_str, "value[, value...]"));
+            }
+
+            let values = args.iter()
+                             .map(|arg| Expr::parse(arg))
+                             .collect::<Result<Vec<_>, _>>()?;
+            Ok(Directive::Data { width, values })
+        },
+
+        ".ascii" | ".asciz" | ".string" => {
+            if args.is_empty() {
+                return Err(operand_count(op_str, "\"string\"[, \"string\"...]"));
+            }
+
+            let mut bytes = Vec::new();
+            for arg in args {
+                bytes.extend(parse_string(arg)?);
+                if op_str != ".ascii" {
+                    bytes.push(0);
+                }
+            }
+            Ok(Directive::Ascii(bytes))
+        },
+
+        ".space" | ".zero" => {
+            match (op_str, args.as_slice()) {
+                (_, [size]) => Ok(Directive::Space { size: Expr::parse(size)?, fill: Expr::Number(0) }),
+                (".space", [size, fill]) => Ok(Directive::Space { size: Expr::parse(size)?, fill: Expr::parse(fill)? }),
+                (".space", _) => Err(operand_count(op_str, "size[, fill]")),
+                _ => Err(operand_count(op_str, "size"))
+            }
+        },
+
+        ".org" => {
+            match args.as_slice() {
+                [offset] => Ok(Directive::Org { offset: Expr::parse(offset)?, fill: Expr::Number(0) }),
+                [offset, fill] => Ok(Directive::Org { offset: Expr::parse(offset)?, fill: Expr::parse(fill)? }),
+                _ => Err(operand_count(op_str, "offset[, fill]"))
+            }
+        },
+
+        ".align" | ".p2align" | ".balign" => {
+            let (amount, fill, max) = match args.as_slice() {
+                [amount] => (amount, None, None),
+                [amount, fill] => (amount, Some(fill), None),
+                [amount, fill, max] => (amount, Some(fill), Some(max)),
+                _ => return Err(operand_count(op_str, "amount[, fill[, max]]"))
+            };
+
+            // `.align 2,,8` leaves the fill empty
+            let optional = |arg: Option<&&str>| match arg {
+                Some(arg) if !arg.trim().is_empty() => Expr::parse(arg).map(Some),
+                _ => Ok(None)
+            };
+            Ok(Directive::Align {
+                amount: Expr::parse(amount)?,
+                bytes: op_str == ".balign",
+                fill: optional(fill)?,
+                max: optional(max)?,
+            })
+        },
+
+        ".fill" => {
+            let (repeat, size, value) = match args.as_slice() {
+                [repeat] => (Expr::parse(repeat)?, Expr::Number(1), Expr::Number(0)),
+                [repeat, size] => (Expr::parse(repeat)?, Expr::parse(size)?, Expr::Number(0)),
+                [repeat, size, value] => (Expr::parse(repeat)?, Expr::parse(size)?, Expr::parse(value)?),
+                _ => return Err(operand_count(op_str, "repeat[, size[, value]]"))
+            };
+            Ok(Directive::Fill { repeat, size, value })
+        },
+
+        ".equ" | ".set" => {
+            match args.as_slice() {
+                [name, value] if is_symbol_name(name) => Ok(Directive::Equ {
+                    name: name.to_string(),
+                    value: Expr::parse(value)?,
+                    redefinable: op_str == ".set",
+                }),
+                [name, _] => Err(AsmRiscVError::InvalidSymbolName(name.to_string())),
+                _ => Err(operand_count(op_str, "name, value"))
+            }
+        },
+
+        ".globl" | ".global" => {
+            if args.is_empty() {
+                return Err(operand_count(op_str, "symbol[, symbol...]"));
+            }
+            Ok(Directive::Globl(args.iter().map(|name| name.to_string()).collect()))
+        },
+
+        _ => Err(AsmRiscVError::UnknownDirective(op_str.to_string()))
+    }
+}
+
+fn operand_count(op_str: &str, expected: &'static str) -> AsmRiscVError {
+    AsmRiscVError::OperandCount { mnemonic: op_str.to_string(), expected }
+}
+
+/// A byte count, which can not be negative
+fn to_size(value: i64, context: &'static str) -> Result<u32, AsmRiscVError> {
+    u32::try_from(value).map_err(|_| AsmRiscVError::ValueOutOfRange { value, min: 0, max: u32::MAX as i64, context })
+}
+
+fn is_symbol_name(name: &str) -> bool {
+    match name.as_bytes().first() {
+        Some(first) if !first.is_ascii_digit() => {
+            name.bytes().all(|b| b.is_ascii_alphanumeric() || b"_.$".contains(&b))
+        },
+        _ => false
+    }
+}
+
+/// `len` copies of `fill`, reporting a size the assembler can not hold instead of aborting
+fn filled(fill: u8, len: u32) -> Result<Vec<u8>, AsmRiscVError> {
+    let mut bytes = Vec::new();
+    bytes.try_reserve_exact(len as usize).map_err(|_| AsmRiscVError::OutOfMemory(len))?;
+    bytes.resize(len as usize, fill);
+    Ok(bytes)
+}
+
+/// `.fill` sizes above 8 are treated as 8
+fn fill_size(size: &Expr, table: &SymbolTable) -> Result<u32, AsmRiscVError> {
+    match size.eval(table)?.absolute()? {
+        size if size < 0 => Err(AsmRiscVError::ValueOutOfRange { value: size, min: 0, max: 8, context: "`.fill` size" }),
+        size => Ok(size.min(8) as u32)
+    }
+}
+
+/// A value must fit the field either as a signed or as an unsigned number
+fn eval_data_value(expr: &Expr, width: usize, table: &SymbolTable) -> Result<i64, AsmRiscVError> {
+    let value = expr.eval(table)?.absolute()?;
+
+    let (min, max, context) = match width {
+        1 => (i8::MIN as i64, u8::MAX as i64, "byte value"),
+        2 => (i16::MIN as i64, u16::MAX as i64, "half-word value"),
+        4 => (i32::MIN as i64, u32::MAX as i64, "word value"),
+        _ => return Ok(value)
+    };
+
+    if !(min..=max).contains(&value) {
+        Err(AsmRiscVError::ValueOutOfRange { value, min, max, context })
+    } else {
+        Ok(value)
+    }
+}
+
+/// Decode a double-quoted string literal with C escape sequences
+fn parse_string(arg: &str) -> Result<Vec<u8>, AsmRiscVError> {
+    let inner = match arg.trim().strip_prefix('"').and_then(|rest| rest.strip_suffix('"')) {
+        Some(inner) => inner,
+        None => return Err(AsmRiscVError::UnexpectedToken { found: arg.trim().to_string(), expected: "a double-quoted string" })
+    };
+
+    let mut bytes = Vec::new();
+    let mut chars = inner.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            let mut buf = [0; 4];
+            bytes.extend_from_slice(c.encode_utf8(&mut buf).as_bytes());
+            continue;
+        }
+
+        let escaped = match chars.next() {
+            Some('n') => b'\n',
+            Some('t') => b'\t',
+            Some('r') => b'\r',
+            Some('a') => 0x07,
+            Some('b') => 0x08,
+            Some('f') => 0x0c,
+            Some('v') => 0x0b,
+            Some('e') => 0x1b,
+            Some('\\') => b'\\',
+            Some('"') => b'"',
+            Some('\'') => b'\'',
+            Some('x') => {
+                let mut value = 0_u32;
+                let mut digits = 0;
+                while let Some(digit) = chars.peek().and_then(|c| c.to_digit(16)) {
+                    value = (value << 4) | digit;
+                    digits += 1;
+                    chars.next();
+                }
+                if digits == 0 {
+                    return Err(AsmRiscVError::InvalidEscape("\\x".to_string()));
+                }
+                value as u8
+            },
+            Some(c @ '0'..='7') => {
+                let mut value = c.to_digit(8).unwrap_or(0);
+                for _ in 0..2 {
+                    match chars.peek().and_then(|c| c.to_digit(8)) {
+                        Some(digit) => {
+                            value = (value << 3) | digit;
+                            chars.next();
+                        },
+                        None => break
+                    }
+                }
+                value as u8
+            },
+            Some(c) => return Err(AsmRiscVError::InvalidEscape(format!("\\{}", c))),
+            None => return Err(AsmRiscVError::InvalidEscape("\\".to_string()))
+        };
+        bytes.push(escaped);
+    }
+
+    Ok(bytes)
+}
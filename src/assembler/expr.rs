@@ -0,0 +1,468 @@
+use super::reloc::{self, RelocKind, Target};
+use super::section::{Label, Section};
+use crate::utils::exception::AsmRiscVError;
+
+use std::collections::HashMap;
+use std::fmt;
+
+/// Labels and named constants visible to expressions
+#[derive(Debug, Default)]
+pub struct SymbolTable {
+    pub labels: HashMap<String, Label>,
+    /// Values defined by `.equ` and `.set`
+    pub constants: HashMap<String, i64>,
+}
+
+impl SymbolTable {
+    pub fn contains(&self, name: &str) -> bool {
+        self.labels.contains_key(name) || self.constants.contains_key(name)
+    }
+
+    pub fn get(&self, name: &str) -> Option<Value> {
+        match self.constants.get(name) {
+            Some(value) => Some(Value { section: None, offset: *value }),
+            None => self.labels.get(name).map(|label| Value { section: Some(label.section), offset: label.offset as i64 })
+        }
+    }
+}
+
+/// Result of evaluating an expression: an absolute number when `section` is `None`,
+/// otherwise an offset from the start of that section
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Value {
+    pub section: Option<Section>,
+    pub offset: i64,
+}
+
+impl Value {
+    pub fn absolute(self) -> Result<i64, AsmRiscVError> {
+        match self.section {
+            None => Ok(self.offset),
+            Some(section) => Err(AsmRiscVError::NotAbsolute(section.name()))
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UnaryOp {
+    Neg,
+    Not,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BinaryOp {
+    Mul,
+    Div,
+    Rem,
+    Add,
+    Sub,
+    Shl,
+    Shr,
+    And,
+    Xor,
+    Or,
+}
+
+impl BinaryOp {
+    /// Binding strength, following C: `* / %` > `+ -` > `<< >>` > `&` > `^` > `|`
+    fn precedence(&self) -> u8 {
+        match self {
+            BinaryOp::Mul | BinaryOp::Div | BinaryOp::Rem => 6,
+            BinaryOp::Add | BinaryOp::Sub => 5,
+            BinaryOp::Shl | BinaryOp::Shr => 4,
+            BinaryOp::And => 3,
+            BinaryOp::Xor => 2,
+            BinaryOp::Or => 1,
+        }
+    }
+
+    fn symbol(&self) -> &'static str {
+        match self {
+            BinaryOp::Mul => "*",
+            BinaryOp::Div => "/",
+            BinaryOp::Rem => "%",
+            BinaryOp::Add => "+",
+            BinaryOp::Sub => "-",
+            BinaryOp::Shl => "<<",
+            BinaryOp::Shr => ">>",
+            BinaryOp::And => "&",
+            BinaryOp::Xor => "^",
+            BinaryOp::Or => "|",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Expr {
+    Number(i64),
+    Symbol(String),
+    Unary(UnaryOp, Box<Expr>),
+    Binary(BinaryOp, Box<Expr>, Box<Expr>),
+    /// `%hi(...)`, `%lo(...)`, `%pcrel_hi(...)`, `%pcrel_lo(...)`
+    Modifier(RelocKind, Box<Expr>),
+}
+
+impl Expr {
+    pub fn parse(expr_str: &str) -> Result<Expr, AsmRiscVError> {
+        let tokens = tokenize(expr_str)?;
+        let mut pos = 0;
+        let expr = parse_binary(&tokens, &mut pos, 0)?;
+
+        if let Some(token) = tokens.get(pos) {
+            return Err(AsmRiscVError::UnexpectedToken { found: token.to_string(), expected: "an operator" });
+        }
+        Ok(expr)
+    }
+
+    pub fn eval(&self, table: &SymbolTable) -> Result<Value, AsmRiscVError> {
+        match self {
+            Expr::Number(value) => Ok(Value { section: None, offset: *value }),
+            Expr::Symbol(name) => table.get(name).ok_or_else(|| AsmRiscVError::UndefinedSymbol(name.clone())),
+            Expr::Unary(op, operand) => {
+                let value = operand.eval(table)?.absolute()?;
+                Ok(Value {
+                    section: None,
+                    offset: match op {
+                        UnaryOp::Neg => value.wrapping_neg(),
+                        UnaryOp::Not => !value,
+                    }
+                })
+            },
+            Expr::Binary(op, lhs, rhs) => {
+                let lhs = lhs.eval(table)?;
+                let rhs = rhs.eval(table)?;
+
+                // Only `rel + abs`, `abs + rel`, `rel - abs` and `rel - rel` within
+                // one section are meaningful for section-relative values
+                let section = match (op, lhs.section, rhs.section) {
+                    (_, None, None) => None,
+                    (BinaryOp::Add, Some(section), None) | (BinaryOp::Add, None, Some(section)) |
+                    (BinaryOp::Sub, Some(section), None) => Some(section),
+                    (BinaryOp::Sub, Some(left), Some(right)) if left == right => None,
+                    _ => return Err(AsmRiscVError::AddressArithmetic(op.symbol()))
+                };
+
+                let (a, b) = (lhs.offset, rhs.offset);
+                let offset = match op {
+                    BinaryOp::Mul => a.wrapping_mul(b),
+                    BinaryOp::Div | BinaryOp::Rem if b == 0 => return Err(AsmRiscVError::DivisionByZero),
+                    BinaryOp::Div => a.wrapping_div(b),
+                    BinaryOp::Rem => a.wrapping_rem(b),
+                    BinaryOp::Add => a.wrapping_add(b),
+                    BinaryOp::Sub => a.wrapping_sub(b),
+                    BinaryOp::Shl => a.wrapping_shl(b as u32),
+                    BinaryOp::Shr => a.wrapping_shr(b as u32),
+                    BinaryOp::And => a & b,
+                    BinaryOp::Xor => a ^ b,
+                    BinaryOp::Or => a | b,
+                };
+                Ok(Value { section, offset })
+            },
+            // Only operators applied to plain numbers have a value before layout;
+            // the rest are turned into relocations by the instruction parser
+            Expr::Modifier(kind, operand) => {
+                let value = operand.eval(table)?.absolute()?;
+                Ok(Value { section: None, offset: reloc::resolve_absolute(*kind, value)? as i64 })
+            }
+        }
+    }
+
+    /// Evaluate into a relocation target plus addend. Unlike `eval`, a symbol that is
+    /// not defined in this file is accepted on its own or with a constant added or subtracted.
+    pub fn eval_target(&self, table: &SymbolTable) -> Result<(Target, i64), AsmRiscVError> {
+        let error = match self.eval(table) {
+            Ok(Value { section: None, offset }) => return Ok((Target::Absolute, offset)),
+            Ok(Value { section: Some(section), offset }) => return Ok((Target::Section(section), offset)),
+            Err(e) => e
+        };
+
+        let (symbol, addend) = match self {
+            Expr::Symbol(_) => (self, 0),
+            Expr::Binary(BinaryOp::Add, lhs, rhs) if matches!(**lhs, Expr::Symbol(_)) => (&**lhs, rhs.eval(table)?.absolute()?),
+            Expr::Binary(BinaryOp::Add, lhs, rhs) if matches!(**rhs, Expr::Symbol(_)) => (&**rhs, lhs.eval(table)?.absolute()?),
+            Expr::Binary(BinaryOp::Sub, lhs, rhs) if matches!(**lhs, Expr::Symbol(_)) => (&**lhs, rhs.eval(table)?.absolute()?.wrapping_neg()),
+            _ => return Err(error)
+        };
+
+        match symbol {
+            Expr::Symbol(name) if !table.contains(name) => Ok((Target::Symbol(name.clone()), addend)),
+            _ => Err(error)
+        }
+    }
+
+    /// Whether evaluating the expression depends on the position of any label
+    pub fn references_label(&self, table: &SymbolTable) -> bool {
+        match self {
+            Expr::Number(_) => false,
+            Expr::Symbol(name) => !table.constants.contains_key(name),
+            Expr::Unary(_, operand) => operand.references_label(table),
+            Expr::Binary(_, lhs, rhs) => lhs.references_label(table) || rhs.references_label(table),
+            Expr::Modifier(_, operand) => operand.references_label(table),
+        }
+    }
+}
+
+/// Parse and evaluate an expression that must produce a plain number
+pub fn eval_absolute(expr_str: &str, table: &SymbolTable) -> Result<i64, AsmRiscVError> {
+    Expr::parse(expr_str)?.eval(table)?.absolute()
+}
+
+/// What may start an operand of an expression
+const OPERAND: &str = "a number, symbol or `(`";
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Number(i64),
+    Symbol(String),
+    Op(BinaryOp),
+    Modifier(RelocKind),
+    Tilde,
+    Open,
+    Close,
+}
+
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Token::Number(value) => write!(f, "{}", value),
+            Token::Symbol(name) => write!(f, "{}", name),
+            Token::Op(op) => write!(f, "{}", op.symbol()),
+            Token::Modifier(kind) => write!(f, "{}", kind.name()),
+            Token::Tilde => write!(f, "~"),
+            Token::Open => write!(f, "("),
+            Token::Close => write!(f, ")"),
+        }
+    }
+}
+
+fn tokenize(expr_str: &str) -> Result<Vec<Token>, AsmRiscVError> {
+    let bytes = expr_str.as_bytes();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+
+    while i < bytes.len() {
+        let c = bytes[i];
+        let token = match c {
+            b' ' | b'\t' => {
+                i += 1;
+                continue;
+            },
+            b'0'..=b'9' => {
+                let start = i;
+                while i < bytes.len() && bytes[i].is_ascii_alphanumeric() {
+                    i += 1;
+                }
+                tokens.push(Token::Number(parse_literal(&expr_str[start..i])?));
+                continue;
+            },
+            b'\'' => {
+                let (value, len) = parse_char(&expr_str[i..])?;
+                i += len;
+                tokens.push(Token::Number(value));
+                continue;
+            },
+            b'a'..=b'z' | b'A'..=b'Z' | b'_' | b'.' | b'$' => {
+                let start = i;
+                while i < bytes.len() && (bytes[i].is_ascii_alphanumeric() || b"_.$".contains(&bytes[i])) {
+                    i += 1;
+                }
+                tokens.push(Token::Symbol(expr_str[start..i].to_string()));
+                continue;
+            },
+            b'<' | b'>' => {
+                if bytes.get(i + 1) != Some(&c) {
+                    return Err(AsmRiscVError::UnexpectedToken { found: (c as char).to_string(), expected: "`<<` or `>>`" });
+                }
+                i += 1;
+                Token::Op(if c == b'<' { BinaryOp::Shl } else { BinaryOp::Shr })
+            },
+            b'%' if bytes.get(i + 1).is_some_and(|b| b.is_ascii_alphabetic()) => {
+                let start = i + 1;
+                i = start;
+                while i < bytes.len() && (bytes[i].is_ascii_alphanumeric() || bytes[i] == b'_') {
+                    i += 1;
+                }
+                let kind = RelocKind::from_name(&expr_str[start..i]).ok_or_else(|| AsmRiscVError::UnknownModifier(expr_str[start - 1..i].to_string()))?;
+                if bytes.get(i) != Some(&b'(') {
+                    return Err(match expr_str[i..].trim_start().chars().next() {
+                        Some(c) => AsmRiscVError::UnexpectedToken { found: c.to_string(), expected: "`(` after a relocation operator" },
+                        None => AsmRiscVError::MissingOperand { expected: "`(` after a relocation operator" }
+                    });
+                }
+                tokens.push(Token::Modifier(kind));
+                continue;
+            },
+            b'*' => Token::Op(BinaryOp::Mul),
+            b'/' => Token::Op(BinaryOp::Div),
+            b'%' => Token::Op(BinaryOp::Rem),
+            b'+' => Token::Op(BinaryOp::Add),
+            b'-' => Token::Op(BinaryOp::Sub),
+            b'&' => Token::Op(BinaryOp::And),
+            b'^' => Token::Op(BinaryOp::Xor),
+            b'|' => Token::Op(BinaryOp::Or),
+            b'~' => Token::Tilde,
+            b'(' => Token::Open,
+            b')' => Token::Close,
+            _ => {
+                let found = expr_str[i..].chars().next().unwrap_or_default().to_string();
+                return Err(AsmRiscVError::UnexpectedToken { found, expected: "an expression" });
+            }
+        };
+        tokens.push(token);
+        i += 1;
+    }
+
+    if tokens.is_empty() {
+        return Err(AsmRiscVError::MissingOperand { expected: "an expression" });
+    }
+    Ok(tokens)
+}
+
+/// Precedence climbing over binary operators binding at least as tight as `min_precedence`
+fn parse_binary(tokens: &[Token], pos: &mut usize, min_precedence: u8) -> Result<Expr, AsmRiscVError> {
+    let mut lhs = parse_unary(tokens, pos)?;
+
+    while let Some(Token::Op(op)) = tokens.get(*pos) {
+        if op.precedence() < min_precedence {
+            break;
+        }
+        *pos += 1;
+        let rhs = parse_binary(tokens, pos, op.precedence() + 1)?;
+        lhs = Expr::Binary(*op, Box::new(lhs), Box::new(rhs));
+    }
+
+    Ok(lhs)
+}
+
+fn parse_unary(tokens: &[Token], pos: &mut usize) -> Result<Expr, AsmRiscVError> {
+    let token = tokens.get(*pos).ok_or(AsmRiscVError::MissingOperand { expected: OPERAND })?;
+    *pos += 1;
+
+    match token {
+        Token::Number(value) => Ok(Expr::Number(*value)),
+        Token::Symbol(name) => Ok(Expr::Symbol(name.clone())),
+        Token::Op(BinaryOp::Sub) => Ok(Expr::Unary(UnaryOp::Neg, Box::new(parse_unary(tokens, pos)?))),
+        Token::Op(BinaryOp::Add) => parse_unary(tokens, pos),
+        Token::Tilde => Ok(Expr::Unary(UnaryOp::Not, Box::new(parse_unary(tokens, pos)?))),
+        // `tokenize` only produces a modifier directly followed by `(`
+        Token::Modifier(kind) => Ok(Expr::Modifier(*kind, Box::new(parse_unary(tokens, pos)?))),
+        Token::Open => {
+            let expr = parse_binary(tokens, pos, 0)?;
+            match tokens.get(*pos) {
+                Some(Token::Close) => {},
+                Some(token) => return Err(AsmRiscVError::UnexpectedToken { found: token.to_string(), expected: "`)`" }),
+                None => return Err(AsmRiscVError::MissingOperand { expected: "`)`" })
+            }
+            *pos += 1;
+            Ok(expr)
+        },
+        _ => Err(AsmRiscVError::UnexpectedToken { found: token.to_string(), expected: OPERAND })
+    }
+}
+
+/// Integer literal with an optional `0x`, `0b` or `0o` prefix in either case
+fn parse_literal(literal: &str) -> Result<i64, AsmRiscVError> {
+    let (base, digits) = match literal.as_bytes() {
+        [b'0', b'x' | b'X', ..] => (16, &literal[2..]),
+        [b'0', b'b' | b'B', ..] => (2, &literal[2..]),
+        [b'0', b'o' | b'O', ..] => (8, &literal[2..]),
+        _ => (10, literal),
+    };
+
+    // Hexadecimal literals may spell out all 64 bits
+    match u64::from_str_radix(digits, base) {
+        Ok(value) if base != 10 || value <= i64::MAX as u64 => Ok(value as i64),
+        _ => Err(AsmRiscVError::InvalidNumber(literal.to_string()))
+    }
+}
+
+/// Character literal such as `'a'` or `'\n'`, returning its value and length in bytes
+fn parse_char(literal: &str) -> Result<(i64, usize), AsmRiscVError> {
+    let invalid = || {
+        let end = literal[1..].find('\'').map(|end| end + 2).unwrap_or(literal.len());
+        AsmRiscVError::InvalidCharacter(literal[..end].to_string())
+    };
+    let mut chars = literal.char_indices().skip(1);
+
+    let value = match chars.next() {
+        Some((_, '\\')) => match chars.next() {
+            Some((_, 'n')) => '\n' as i64,
+            Some((_, 't')) => '\t' as i64,
+            Some((_, 'r')) => '\r' as i64,
+            Some((_, '0')) => 0,
+            Some((_, c @ ('\\' | '\'' | '"'))) => c as i64,
+            _ => return Err(invalid())
+        },
+        Some((_, '\'')) | None => return Err(invalid()),
+        Some((_, c)) => c as i64,
+    };
+
+    match chars.next() {
+        Some((end, '\'')) => Ok((value, end + 1)),
+        _ => Err(invalid())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Expr, SymbolTable, Value};
+    use crate::assembler::reloc::Target;
+    use crate::assembler::section::{Label, Section};
+    use crate::utils::exception::AsmRiscVError;
+
+    fn table() -> SymbolTable {
+        let mut table = SymbolTable::default();
+        table.labels.insert("start".to_string(), Label { section: Section::Text, offset: 4 });
+        table.labels.insert("end".to_string(), Label { section: Section::Text, offset: 0x24 });
+        table.labels.insert("buffer".to_string(), Label { section: Section::Data, offset: 0x10 });
+        table.constants.insert("SIZE".to_string(), 3);
+        table
+    }
+
+    fn eval(expr: &str) -> Result<Value, AsmRiscVError> {
+        Expr::parse(expr)?.eval(&table())
+    }
+
+    fn absolute(offset: i64) -> Value {
+        Value { section: None, offset }
+    }
+
+    #[test]
+    fn label_differences_are_constants() {
+        assert_eq!(eval("end - start").unwrap(), absolute(0x20));
+        assert_eq!(eval("(end - start) / 4").unwrap(), absolute(8));
+        assert_eq!(eval("end + SIZE - start").unwrap(), absolute(0x23));
+        assert_eq!(eval("start - end").unwrap(), absolute(-0x20));
+        assert_eq!(eval("end - 4").unwrap(), Value { section: Some(Section::Text), offset: 0x20 });
+        assert_eq!(eval("SIZE + buffer").unwrap(), Value { section: Some(Section::Data), offset: 0x13 });
+    }
+
+    #[test]
+    fn address_arithmetic_stays_in_one_section() {
+        assert!(matches!(eval("buffer - start"), Err(AsmRiscVError::AddressArithmetic("-"))));
+        assert!(matches!(eval("start + end"), Err(AsmRiscVError::AddressArithmetic("+"))));
+        assert!(matches!(eval("start * 2"), Err(AsmRiscVError::AddressArithmetic("*"))));
+        assert!(matches!(eval("SIZE - start"), Err(AsmRiscVError::AddressArithmetic("-"))));
+        assert!(matches!(eval("-start"), Err(AsmRiscVError::NotAbsolute(".text"))));
+    }
+
+    #[test]
+    fn hi_and_lo_round_at_the_sign_of_the_low_part() {
+        assert_eq!(eval("%hi(0x7ff)").unwrap(), absolute(0));
+        assert_eq!(eval("%lo(0x7ff)").unwrap(), absolute(0x7ff));
+        assert_eq!(eval("%hi(0x800)").unwrap(), absolute(1));
+        assert_eq!(eval("%lo(0x800)").unwrap(), absolute(-0x800));
+        assert_eq!(eval("(%hi(0x12345fff) << 12) + %lo(0x12345fff)").unwrap(), absolute(0x12345fff));
+        assert!(matches!(eval("%pcrel_hi(4)"), Err(AsmRiscVError::MisplacedModifier { .. })));
+    }
+
+    #[test]
+    fn unknown_symbols_become_relocation_targets() {
+        let table = table();
+        let target = |expr: &str| Expr::parse(expr).unwrap().eval_target(&table);
+        assert_eq!(target("printf + 8").unwrap(), (Target::Symbol("printf".to_string()), 8));
+        assert_eq!(target("printf - SIZE").unwrap(), (Target::Symbol("printf".to_string()), -3));
+        assert_eq!(target("buffer + 4").unwrap(), (Target::Section(Section::Data), 0x14));
+        assert!(matches!(target("printf * 2"), Err(AsmRiscVError::UndefinedSymbol(_))));
+    }
+}
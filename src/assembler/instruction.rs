@@ -1,4 +1,4 @@
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Instruction {
     Itype {rd: u32, rs1: u32, imm: i32, opcode: u32, funct3: u32},
     Rtype {rd: u32, rs1: u32, rs2: u32, opcode: u32, funct3: u32, funct7: u32},
@@ -6,4 +6,106 @@ pub enum Instruction {
     Btype {rs1: u32, rs2: u32, imm: i32, opcode: u32, funct3: u32},
     Utype {rd: u32, imm: i32, opcode: u32},
     Jtype {rd: u32, imm: i32, opcode: u32},
-}
\ No newline at end of file
+}
+
+impl Instruction {
+    /// 32-bit machine code word
+    pub fn encode(&self) -> u32 {
+        match *self {
+            // I-type: imm[11:0] | rs1[4:0] | funct3[2:0] | rd[4:0] | opcode[6:0]
+            Instruction::Itype {rd, rs1, imm, opcode, funct3} => {
+                ((imm << 20) as u32) | (rs1 << 15) | (funct3 << 12) | (rd << 7) | opcode
+            },
+
+            // R-type: funct[6:0] | rs2[4:0] | rs1[4:0] | funct3[2:0] | rd[4:0] | opcode[6:0]
+            Instruction::Rtype {rd, rs1, rs2, opcode, funct3, funct7} => {
+                (funct7 << 25) | (rs2 << 20) | (rs1 << 15) | (funct3 << 12) | (rd << 7) | opcode
+            },
+
+            // S-type: imm[11:5] | rs2[4:0] | rs1[4:0] | funct3[2:0] | imm[4:0] | opcode[6:0]
+            Instruction::Stype {rs1, rs2, imm, opcode, funct3} => {
+                (((imm & 0xfe0) << 20) as u32) | (rs2 << 20) | (rs1 << 15) | (funct3 << 12) | (((imm & 0x1f) << 7) as u32) | opcode
+            },
+
+            // B-type: imm[12] | imm[10:5] | rs2[4:0] | rs1[4:0] | funct3[2:0] | imm[4:1] | imm[11] | opcode[6:0]
+            Instruction::Btype {rs1, rs2, imm, opcode, funct3} => {
+                ((((imm & 0x1000) << 19) | ((imm & 0x07e0) << 20)) as u32) | (rs2 << 20) | (rs1 << 15) | (funct3 << 12) | ((((imm & 0x01e) << 7) | ((imm & 0x800) >> 4)) as u32) | opcode
+            },
+
+            // U-type: imm[31:12] | rd[4:0] | opcode[6:0]
+            Instruction::Utype {rd, imm, opcode} => {
+                (((imm & 0x000fffff) << 12) as u32) | (rd << 7) | opcode
+            },
+
+            // J-type: imm[20] | imm[10:1] | imm[11] | imm[19:12] | rd[4:0] | opcode[6:0]
+            Instruction::Jtype {rd, imm, opcode} => {
+                ((((imm & 0x100000) << 11) | ((imm & 0x0007fe) << 20) | ((imm & 0x000800) << 9) | (imm & 0x0ff000)) as u32) | (rd << 7) | opcode
+            },
+        }
+    }
+
+    /// Name of the base instruction, or `None` for field values the parser never produces
+    pub fn mnemonic(&self) -> Option<&'static str> {
+        let name = match *self {
+            Instruction::Itype {opcode: 0b0010011, funct3, imm, ..} => match (funct3, imm >> 5) {
+                (0b000, _) => "addi",
+                (0b010, _) => "slti",
+                (0b011, _) => "sltiu",
+                (0b100, _) => "xori",
+                (0b110, _) => "ori",
+                (0b111, _) => "andi",
+                (0b001, 0b0000000) => "slli",
+                (0b101, 0b0000000) => "srli",
+                (0b101, 0b0100000) => "srai",
+                _ => return None
+            },
+            Instruction::Itype {opcode: 0b0000011, funct3, ..} => match funct3 {
+                0b000 => "lb",
+                0b001 => "lh",
+                0b010 => "lw",
+                0b100 => "lbu",
+                0b101 => "lhu",
+                _ => return None
+            },
+            Instruction::Itype {opcode: 0b1100111, funct3: 0b000, ..} => "jalr",
+            Instruction::Itype {opcode: 0b1110011, funct3: 0b000, rd: 0, rs1: 0, imm} => match imm {
+                0 => "ecall",
+                1 => "ebreak",
+                _ => return None
+            },
+            Instruction::Rtype {opcode: 0b0110011, funct3, funct7, ..} => match (funct3, funct7) {
+                (0b000, 0b0000000) => "add",
+                (0b000, 0b0100000) => "sub",
+                (0b001, 0b0000000) => "sll",
+                (0b010, 0b0000000) => "slt",
+                (0b011, 0b0000000) => "sltu",
+                (0b100, 0b0000000) => "xor",
+                (0b101, 0b0000000) => "srl",
+                (0b101, 0b0100000) => "sra",
+                (0b110, 0b0000000) => "or",
+                (0b111, 0b0000000) => "and",
+                _ => return None
+            },
+            Instruction::Stype {opcode: 0b0100011, funct3, ..} => match funct3 {
+                0b000 => "sb",
+                0b001 => "sh",
+                0b010 => "sw",
+                _ => return None
+            },
+            Instruction::Btype {opcode: 0b1100011, funct3, ..} => match funct3 {
+                0b000 => "beq",
+                0b001 => "bne",
+                0b100 => "blt",
+                0b101 => "bge",
+                0b110 => "bltu",
+                0b111 => "bgeu",
+                _ => return None
+            },
+            Instruction::Utype {opcode: 0b0110111, ..} => "lui",
+            Instruction::Utype {opcode: 0b0010111, ..} => "auipc",
+            Instruction::Jtype {opcode: 0b1101111, ..} => "jal",
+            _ => return None
+        };
+        Some(name)
+    }
+}
@@ -0,0 +1,144 @@
+use super::directive::Directive;
+use super::expr::SymbolTable;
+use super::parser;
+use super::section::{Label, Section};
+use crate::utils::diagnostic::{Diagnostic, Spanned};
+use crate::utils::exception::AsmRiscVError;
+
+use std::collections::HashMap;
+
+/// What a statement places in its section
+#[derive(Debug)]
+pub enum Statement {
+    Directive(Directive),
+    /// A real or pseudo instruction, parsed again once every label is known
+    Instruction,
+    /// A line holding only a label or nothing at all
+    Empty,
+}
+
+/// One statement and the bytes it occupies
+#[derive(Debug)]
+pub struct Item<'a> {
+    pub line: &'a str,
+    /// 1-based line of the source file the statement is on
+    pub line_number: usize,
+    /// Byte offset of the statement in that line
+    pub column: usize,
+    /// Section and byte offset of the statement's first byte
+    pub location: Label,
+    pub size: u32,
+    pub statement: Statement,
+}
+
+impl Item<'_> {
+    /// Locate an error about this statement in the source file
+    pub fn diagnostic(&self, error: impl Into<Spanned>) -> Diagnostic {
+        Diagnostic::new(error, self.line_number, self.column, self.line.len())
+    }
+}
+
+/// Every statement of a file placed at its byte offset within its section
+#[derive(Debug, Default)]
+pub struct Layout<'a> {
+    pub items: Vec<Item<'a>>,
+    /// Final size of each section, indexed by `Section::index`
+    pub sizes: [u32; 4],
+    /// Largest boundary any alignment directive asked for in each section
+    pub alignments: [u32; 4],
+    /// Line each label is defined on
+    pub labels: HashMap<String, usize>,
+}
+
+/// Assign each statement its location and define every label in `table`.
+/// Constants from `.equ`/`.set` are defined in source order as they are reached.
+/// A statement that can not be placed, including one that would push the sections past the
+/// end of the address space, is reported in `diagnostics` and takes no space.
+pub fn layout<'a>(lines: &[(usize, usize, &'a str)], table: &mut SymbolTable, diagnostics: &mut Vec<Diagnostic>) -> Layout<'a> {
+    let mut layout = Layout { alignments: [1; 4], ..Layout::default() };
+    let mut section = Section::Text;
+
+    for &(line_number, column, line) in lines {
+        let location = Label { section, offset: layout.sizes[section.index()] };
+        let diagnostic = |error| Diagnostic::new(error, line_number, column, line.len());
+        match parser::parse_label(line, table, location) {
+            Ok(name) => {
+                layout.labels.insert(name, line_number);
+            },
+            Err(Spanned { error: AsmRiscVError::ParseEmptyLine, .. }) => {},
+            Err(e) => diagnostics.push(diagnostic(e))
+        }
+
+        let (statement, size) = match parser::parse_directive(line) {
+            Ok(Some(directive)) => {
+                match place_directive(&directive, table, location, &mut layout.alignments) {
+                    Ok(size) => {
+                        if let Directive::Section(next) = directive {
+                            section = next;
+                        }
+                        (Statement::Directive(directive), size)
+                    },
+                    Err(e) => {
+                        diagnostics.push(diagnostic(e.into()));
+                        (Statement::Empty, 0)
+                    }
+                }
+            },
+            Ok(None) => (Statement::Instruction, 4 * parser::instruction_len(line, table) as u32),
+            Err(Spanned { error: AsmRiscVError::ParseEmptyLine, .. }) => (Statement::Empty, 0),
+            Err(e) => {
+                diagnostics.push(diagnostic(e));
+                (Statement::Empty, 0)
+            }
+        };
+
+        // The linker places every section in the same address space, so they must fit it together
+        let others: u64 = layout.sizes.iter().map(|&size| size as u64).sum::<u64>() - location.offset as u64;
+        let (statement, size) = match location.offset.checked_add(size).filter(|&end| others + end as u64 <= u32::MAX as u64) {
+            Some(end) => {
+                layout.sizes[location.section.index()] = end;
+                (statement, size)
+            },
+            None => {
+                diagnostics.push(diagnostic(AsmRiscVError::AddressOverflow.into()));
+                (Statement::Empty, 0)
+            }
+        };
+        layout.items.push(Item { line, line_number, column, location, size, statement });
+    }
+
+    layout
+}
+
+/// Define the constant of an `.equ`/`.set`, record the alignment the directive asks for
+/// and return the number of bytes it occupies at `location`
+fn place_directive(directive: &Directive, table: &mut SymbolTable, location: Label, alignments: &mut [u32; 4]) -> Result<u32, AsmRiscVError> {
+    if let Directive::Equ { name, value, redefinable } = directive {
+        super::define_constant(table, name.clone(), value, *redefinable)?;
+    }
+    let alignment = &mut alignments[location.section.index()];
+    *alignment = (*alignment).max(directive.alignment(table)?);
+    directive.size(table, location)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::layout;
+    use crate::assembler::expr::SymbolTable;
+    use crate::assembler::parser;
+    use crate::assembler::section::Section;
+    use crate::utils::exception::AsmRiscVError;
+
+    #[test]
+    fn sections_share_the_address_space() {
+        let lines = parser::split_statements(".data\n.space 0x80000000\n.text\n.fill 0x40000000, 2\n.space 0x7fffffff\n");
+        let mut diagnostics = Vec::new();
+        let layout = layout(&lines, &mut SymbolTable::default(), &mut diagnostics);
+
+        assert_eq!(diagnostics.len(), 1, "{:?}", diagnostics);
+        assert!(matches!(diagnostics[0].error, AsmRiscVError::AddressOverflow));
+        assert_eq!(diagnostics[0].line, 4);
+        assert_eq!(layout.sizes[Section::Data.index()], 0x80000000);
+        assert_eq!(layout.sizes[Section::Text.index()], 0x7fffffff);
+    }
+}
@@ -1,85 +1,329 @@
+use super::directive::{self, Directive};
+use super::expr::{Expr, SymbolTable};
 use super::instruction::Instruction;
+use super::pseudo;
+use super::register::{self, Lookup};
+use super::reloc::{self, RelocKind, Relocation, Target};
+use super::section::Label;
+use crate::utils::diagnostic::Spanned;
 use crate::utils::exception::AsmRiscVError;
 
-use std::collections::HashMap;
+use std::ops::Range;
 
+/// An error and the operand it is about, while the operand is still a slice of the line being parsed
+pub(super) struct TokenError<'a> {
+    pub error: AsmRiscVError,
+    pub token: Option<&'a str>,
+}
+
+impl From<AsmRiscVError> for TokenError<'_> {
+    fn from(error: AsmRiscVError) -> Self {
+        TokenError { error, token: None }
+    }
+}
+
+/// Attach `token` to an error
+pub(super) fn at<'a>(token: &'a str) -> impl FnOnce(AsmRiscVError) -> TokenError<'a> {
+    move |error| TokenError { error, token: Some(token) }
+}
+
+/// Operands of an instruction handed out one at a time, remembering the last one
+/// so an error can point at it. Past the last operand, an empty slice at the end
+/// of the operand list stands for the missing one.
+struct Operands<'a> {
+    tokens: std::vec::IntoIter<&'a str>,
+    end: &'a str,
+    current: Option<&'a str>,
+}
+
+impl<'a> Operands<'a> {
+    fn new(args_str: &'a str) -> Self {
+        Operands { tokens: split_operands(args_str).into_iter(), end: &args_str[args_str.len()..], current: None }
+    }
+
+    fn next(&mut self) -> Option<&'a str> {
+        let token = self.tokens.next();
+        self.current = Some(token.unwrap_or(self.end));
+        token
+    }
+}
+
+/// Byte offset of `part` in `text`, when `part` is a slice of it
+fn offset_in(text: &str, part: &str) -> Option<usize> {
+    let start = (part.as_ptr() as usize).checked_sub(text.as_ptr() as usize)?;
+    (start + part.len() <= text.len()).then_some(start)
+}
+
+/// Bytes of `line` a token of its pre-processed form `valid_line` came from.
+/// Tokens of an expanded pseudo-instruction are looked for by their text.
+fn locate(line: &str, valid_line: &str, token: &str) -> Option<Range<usize>> {
+    let start = match offset_in(valid_line, token) {
+        Some(start) => start,
+        None if !token.is_empty() => valid_line.find(token)?,
+        None => return None
+    };
+    let lead = line.len() - line.trim_start().len();
+    Some(lead + start..lead + start + token.len())
+}
+
+/// Turn an error about a token of `valid_line` into one about bytes of `line`
+fn spanned<'a>(line: &'a str, valid_line: &'a str) -> impl Fn(TokenError) -> Spanned + 'a {
+    move |e| Spanned { error: e.error, span: e.token.and_then(|token| locate(line, valid_line, token)) }
+}
+
+/// Split source text into statements with their 1-based line number and the byte
+/// column they start at: `#` starts a comment and `;` separates statements on the
+/// same line, except inside string and character literals.
+pub fn split_statements(source: &str) -> Vec<(usize, usize, &str)> {
+    source.lines()
+          .enumerate()
+          .flat_map(|(i, line)| {
+              let clean_line = find_unquoted(line, '#').map(|comment| &line[..comment]).unwrap_or(line);
+              split_unquoted(clean_line, ';').into_iter()
+                                             .map(|token| token.trim())
+                                             .filter(|token| !token.is_empty())
+                                             .map(move |token| (i + 1, offset_in(line, token).unwrap_or(0), token))
+          })
+          .collect()
+}
+
+/// Split an operand list on commas that are not inside literals
+pub(super) fn split_operands(args_str: &str) -> Vec<&str> {
+    if args_str.trim().is_empty() {
+        return Vec::new();
+    }
+    split_unquoted(args_str, ',').into_iter().map(|arg| arg.trim()).collect()
+}
+
+fn split_unquoted(line: &str, separator: char) -> Vec<&str> {
+    let mut parts = Vec::new();
+    let mut rest = line;
+    while let Some(pos) = find_unquoted(rest, separator) {
+        parts.push(&rest[..pos]);
+        rest = &rest[pos + separator.len_utf8()..];
+    }
+    parts.push(rest);
+    parts
+}
+
+/// Byte position of the first `target` outside of `"..."` and `'...'` literals
+fn find_unquoted(line: &str, target: char) -> Option<usize> {
+    let mut quote = None;
+    let mut escaped = false;
+
+    for (i, c) in line.char_indices() {
+        match quote {
+            Some(_) if escaped => escaped = false,
+            Some(_) if c == '\\' => escaped = true,
+            Some(q) if c == q => quote = None,
+            Some(_) => {},
+            None if c == target => return Some(i),
+            None if c == '"' || c == '\'' => quote = Some(c),
+            None => {}
+        }
+    }
+
+    None
+}
+
+/// Trim, drop the comment and lowercase the mnemonic or directive name.
+/// Symbol names keep their spelling, as they have to match those of C code;
+/// registers, numbers and relocation operators are matched in any case.
 fn line_pre_process(line: &str) -> Result<String, AsmRiscVError> {
-    let clean_line = line.trim();
+    let clean_line = match find_unquoted(line, '#') {
+        Some(comment) => line[..comment].trim(),
+        None => line.trim()
+    };
     
     if clean_line.is_empty() {
         return Err(AsmRiscVError::ParseEmptyLine);
     }
 
-    if let Some(comment) = clean_line.find('#') {
-        let valid_line = clean_line[..comment].to_lowercase();
-        if valid_line.is_empty() {
-            Err(AsmRiscVError::ParseEmptyLine)
-        } else {
-            Ok(valid_line)
-        }
-    } else {
-        Ok(clean_line.to_lowercase())
-    }
+    let operation = split_label(clean_line).1.trim_start();
+    let start = clean_line.len() - operation.len();
+    let end = start + operation.find(char::is_whitespace).unwrap_or(operation.len());
+    let mut valid_line = clean_line.to_string();
+    valid_line[start..end].make_ascii_lowercase();
+
+    Ok(valid_line)
 }
 
-pub fn parse_label(line: &str, table: &mut HashMap<String, i32>, ins_line_num: usize) -> Result<(), AsmRiscVError> {
+pub fn parse_label(line: &str, table: &mut SymbolTable, label: Label) -> Result<String, Spanned> {
     let valid_line = line_pre_process(line)?;
+    let spanned = spanned(line, &valid_line);
     
-    match valid_line.split_once(':')  {
-        Some((label_str, _)) => {
+    match split_label(&valid_line)  {
+        (Some(label_str), _) => {
             let clean_label = label_str.trim();
-            if clean_label.contains(' ') || clean_label.as_bytes()[0].is_ascii_digit() {
-                return Err(AsmRiscVError::SyntaxError);
+            if clean_label.is_empty() || clean_label.contains(char::is_whitespace) || clean_label.as_bytes()[0].is_ascii_digit() {
+                return Err(spanned(at(label_str)(AsmRiscVError::InvalidSymbolName(clean_label.to_string()))));
             }
-            if table.contains_key(clean_label) {
-                return Err(AsmRiscVError::UsedLabel);
+            if table.contains(clean_label) {
+                return Err(spanned(at(clean_label)(AsmRiscVError::DuplicateSymbol(clean_label.to_string()))));
             }
-            table.insert(clean_label.to_string(), ins_line_num as i32);
-            Ok(())
+            table.labels.insert(clean_label.to_string(), label);
+            Ok(clean_label.to_string())
         },
-        None => {
-            Err(AsmRiscVError::ParseEmptyLine)
+        (None, _) => {
+            Err(AsmRiscVError::ParseEmptyLine.into())
         }
     }
 }
 
-pub fn parse_instruction(line: &str, table: &HashMap<String, i32>, ins_count: usize) -> Result<Instruction, AsmRiscVError> {
+/// Parse the directive on `line`, or `Ok(None)` when the line holds an instruction
+pub fn parse_directive(line: &str) -> Result<Option<Directive>, Spanned> {
     let valid_line = line_pre_process(line)?;
+    let (op_str, args_str) = split_operation(strip_label(&valid_line)?);
 
-    let last_line = match valid_line.split_once(':')  {
-        Some((_, right)) => {
-            if right.trim().is_empty() {
-                return Err(AsmRiscVError::ParseEmptyLine);
-            }
-            right.trim()
+    if !op_str.starts_with('.') {
+        return Ok(None);
+    }
+
+    directive::parse_directive(op_str, args_str).map(Some).map_err(|error| {
+        // Point at the name of an unknown directive and at the operands of a known one
+        let token = match error {
+            AsmRiscVError::UnknownDirective(_) => op_str,
+            _ if args_str.is_empty() => op_str,
+            _ => args_str
+        };
+        spanned(line, &valid_line)(at(token)(error))
+    })
+}
+
+/// Parse the instruction on `line` located at `pc`, expanding pseudo-instructions.
+/// Fields that depend on final addresses are left zero and recorded in `relocations`.
+pub fn parse_instruction(line: &str, table: &SymbolTable, pc: Label, relocations: &mut Vec<Relocation>) -> Result<Vec<Instruction>, Spanned> {
+    let valid_line = line_pre_process(line)?;
+    let spanned = spanned(line, &valid_line);
+    let (op_str, args_str) = split_operation(strip_label(&valid_line)?);
+
+    match pseudo::expand(op_str, args_str, table, pc, relocations).map_err(&spanned)? {
+        Some(expansion) => {
+            expansion.iter()
+                     .enumerate()
+                     .map(|(i, real_line)| {
+                         let (op_str, args_str) = split_operation(real_line);
+                         let pc = Label { offset: pc.offset + 4 * i as u32, ..pc };
+                         parse_base_instruction(op_str, args_str, table, pc, relocations).map_err(&spanned)
+                     })
+                     .collect()
         },
         None => {
-            &valid_line
+            Ok(vec![parse_base_instruction(op_str, args_str, table, pc, relocations).map_err(&spanned)?])
         }
+    }
+}
+
+/// Number of real instructions `line` occupies once pseudo-instructions are expanded.
+/// Lines that cannot be parsed count as one instruction and are reported by `parse_instruction`.
+pub fn instruction_len(line: &str, table: &SymbolTable) -> usize {
+    let Ok(valid_line) = line_pre_process(line) else {
+        return 0;
     };
 
-    let op_str;
-    let args_str;
-    match last_line.split_once(' ') {
-        Some((left, right)) => {
-            op_str = left;
-            args_str = right;
+    match strip_label(&valid_line) {
+        Ok(last_line) => {
+            let (op_str, args_str) = split_operation(last_line);
+            pseudo::expanded_len(op_str, args_str, table).unwrap_or(1)
         },
-        None => {
-            op_str = &last_line;
-            args_str = "";
+        Err(_) => 0
+    }
+}
+
+/// Separate a leading `label:` from the rest of the line.
+/// A colon that follows a literal does not start a label.
+fn split_label(valid_line: &str) -> (Option<&str>, &str) {
+    match valid_line.split_once(':') {
+        Some((label, rest)) if !label.contains(['"', '\'']) => (Some(label), rest),
+        _ => (None, valid_line)
+    }
+}
+
+fn strip_label(valid_line: &str) -> Result<&str, AsmRiscVError> {
+    match split_label(valid_line) {
+        (Some(_), right) => {
+            if right.trim().is_empty() {
+                return Err(AsmRiscVError::ParseEmptyLine);
+            }
+            Ok(right.trim())
+        },
+        (None, line) => {
+            Ok(line.trim())
         }
-    };
+    }
+}
 
-    let mut tokens = args_str.split(',');
-    
+fn split_operation(line: &str) -> (&str, &str) {
+    match line.split_once(char::is_whitespace) {
+        Some((left, right)) => (left, right.trim()),
+        None => (line, "")
+    }
+}
+
+/// Encode one real instruction. An error points at the operand being parsed when it
+/// happened, or at the mnemonic when no operand was.
+fn parse_base_instruction<'a>(op_str: &'a str, args_str: &'a str, table: &SymbolTable, pc: Label, relocations: &mut Vec<Relocation>) -> Result<Instruction, TokenError<'a>> {
+    let mut tokens = Operands::new(args_str);
+    let instruction = encode_base_instruction(op_str, &mut tokens, table, pc, relocations)
+                          .map_err(|error| TokenError { error, token: Some(tokens.current.unwrap_or(op_str)) })?;
+
+    match tokens.next() {
+        Some(extra) => Err(at(extra)(AsmRiscVError::OperandCount { mnemonic: op_str.to_string(), expected: operand_form(op_str) })),
+        None => Ok(instruction)
+    }
+}
+
+/// Operands a real instruction takes, as shown in error messages
+fn operand_form(op_str: &str) -> &'static str {
+    match op_str {
+        "add" | "sub" | "sll" | "srl" | "sra" | "slt" | "sltu" | "xor" | "or" | "and" => "rd, rs1, rs2",
+        "lb" | "lh" | "lw" | "lbu" | "lhu" | "jalr" => "rd, offset(rs1)",
+        "sb" | "sh" | "sw" => "rs2, offset(rs1)",
+        "beq" | "bne" | "blt" | "bge" | "bltu" | "bgeu" => "rs1, rs2, target",
+        "lui" | "auipc" => "rd, imm",
+        "jal" => "rd, target",
+        "ecall" | "ebreak" => "",
+        _ => "rd, rs1, imm"
+    }
+}
+
+/// Immediate fields of the base instruction formats
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Field {
+    I,
+    S,
+    Shamt,
+    U,
+}
+
+impl Field {
+    fn name(&self) -> &'static str {
+        match self {
+            Field::I => "I-type immediate",
+            Field::S => "S-type immediate",
+            Field::Shamt => "shift amount",
+            Field::U => "U-type immediate",
+        }
+    }
+
+    /// Values the field accepts. U-type immediates may be written signed or unsigned.
+    fn range(&self) -> (i64, i64) {
+        match self {
+            Field::I | Field::S => (-2048, 2047),
+            Field::Shamt => (0, 31),
+            Field::U => (-(1 << 19), 0xfffff),
+        }
+    }
+}
+
+fn encode_base_instruction(op_str: &str, tokens: &mut Operands, table: &SymbolTable, pc: Label, relocations: &mut Vec<Relocation>) -> Result<Instruction, AsmRiscVError> {
     match op_str {
         "addi" | "slti" | "sltiu" | 
         "xori" | "ori" | "andi" => {
             Ok(Instruction::Itype {
                 rd: parse_register(tokens.next())?,
                 rs1: parse_register(tokens.next())?,
-                imm: parse_immediate(tokens.next(), false, false)?,
+                imm: parse_immediate(tokens.next(), Field::I, table, pc, relocations)?,
                 opcode: 0b0010011, 
                 funct3: match op_str {
                     "addi" => 0b000,
@@ -88,7 +332,7 @@ pub fn parse_instruction(line: &str, table: &HashMap<String, i32>, ins_count: us
                     "xori" => 0b100,
                     "ori" => 0b110,
                     "andi" => 0b111, 
-                    _ => return Err(AsmRiscVError::ParseFunctError)
+                    _ => return Err(AsmRiscVError::UnknownInstruction(op_str.to_string()))
                 }
             })
         },
@@ -100,13 +344,13 @@ pub fn parse_instruction(line: &str, table: &HashMap<String, i32>, ins_count: us
                 imm: (match op_str {
                     "slli" | "srli" => 0b000000,
                     "srai" => 0b0100000,
-                    _ => return Err(AsmRiscVError::ParseFunctError)
-                } << 5) | (parse_immediate(tokens.next(), true, false)?),
+                    _ => return Err(AsmRiscVError::UnknownInstruction(op_str.to_string()))
+                } << 5) | (parse_immediate(tokens.next(), Field::Shamt, table, pc, relocations)?),
                 opcode: 0b0010011, 
                 funct3: match op_str {
                     "slli" => 0b001,
                     "srli" | "srai" => 0b101,
-                    _ => return Err(AsmRiscVError::ParseFunctError)
+                    _ => return Err(AsmRiscVError::UnknownInstruction(op_str.to_string()))
                 }
             })
         },
@@ -121,7 +365,7 @@ pub fn parse_instruction(line: &str, table: &HashMap<String, i32>, ins_count: us
                 funct7: match op_str {
                     "add" => 0b0000000,
                     "sub" => 0b0100000,
-                    _ => return Err(AsmRiscVError::ParseFunctError)
+                    _ => return Err(AsmRiscVError::UnknownInstruction(op_str.to_string()))
                 }
             })
         },
@@ -135,12 +379,12 @@ pub fn parse_instruction(line: &str, table: &HashMap<String, i32>, ins_count: us
                 funct3: match op_str {
                     "sll" => 0b001,
                     "srl" | "sra" => 0b101,
-                    _ => return Err(AsmRiscVError::ParseFunctError)
+                    _ => return Err(AsmRiscVError::UnknownInstruction(op_str.to_string()))
                 },
                 funct7: match op_str {
                     "sll" | "srl" => 0b0000000,
                     "sra" => 0b0100000,
-                    _ => return Err(AsmRiscVError::ParseFunctError)
+                    _ => return Err(AsmRiscVError::UnknownInstruction(op_str.to_string()))
                 }, 
             })
         }
@@ -158,7 +402,7 @@ pub fn parse_instruction(line: &str, table: &HashMap<String, i32>, ins_count: us
                     "xor" => 0b100,
                     "or" => 0b110,
                     "and" => 0b111, 
-                    _ => return Err(AsmRiscVError::ParseFunctError)
+                    _ => return Err(AsmRiscVError::UnknownInstruction(op_str.to_string()))
                 },
                 funct7: 0b0000000
             })
@@ -167,7 +411,7 @@ pub fn parse_instruction(line: &str, table: &HashMap<String, i32>, ins_count: us
         "lb" | "lh" | "lw" | 
         "lbu" | "lhu" => {
             let rd = parse_register(tokens.next())?;
-            let (imm, rs1) = parse_parenthesis(tokens.next())?;
+            let (imm, rs1) = parse_parenthesis(tokens.next(), Field::I, table, pc, relocations)?;
             Ok(Instruction::Itype {
                 rd,
                 rs1,
@@ -179,14 +423,14 @@ pub fn parse_instruction(line: &str, table: &HashMap<String, i32>, ins_count: us
                     "lw" => 0b010,
                     "lbu" => 0b100,
                     "lhu" => 0b101,
-                    _ => return Err(AsmRiscVError::ParseFunctError)
+                    _ => return Err(AsmRiscVError::UnknownInstruction(op_str.to_string()))
                 }
             })
         },
 
         "sb" | "sh" | "sw" => {
             let rs2 = parse_register(tokens.next())?;
-            let (imm, rs1) = parse_parenthesis(tokens.next())?;
+            let (imm, rs1) = parse_parenthesis(tokens.next(), Field::S, table, pc, relocations)?;
             Ok(Instruction::Stype {
                 rs2,
                 rs1,
@@ -196,7 +440,7 @@ pub fn parse_instruction(line: &str, table: &HashMap<String, i32>, ins_count: us
                     "sb" => 0b000,
                     "sh" => 0b001,
                     "sw" => 0b010,
-                    _ => return Err(AsmRiscVError::ParseFunctError)
+                    _ => return Err(AsmRiscVError::UnknownInstruction(op_str.to_string()))
                 }
             })
         }
@@ -207,7 +451,7 @@ pub fn parse_instruction(line: &str, table: &HashMap<String, i32>, ins_count: us
             Ok(Instruction::Btype { 
                 rs1: parse_register(tokens.next())?, 
                 rs2: parse_register(tokens.next())?, 
-                imm: parse_label_imm(tokens.next(), table, ins_count)?, 
+                imm: parse_label_imm(tokens.next(), table, pc, 13, relocations)?, 
                 opcode: 0b1100011, 
                 funct3: match op_str {
                     "beq" => 0b000,
@@ -216,7 +460,7 @@ pub fn parse_instruction(line: &str, table: &HashMap<String, i32>, ins_count: us
                     "bge" => 0b101,
                     "bltu" => 0b110,
                     "bgeu" => 0b111,
-                    _ => return Err(AsmRiscVError::ParseFunctError)
+                    _ => return Err(AsmRiscVError::UnknownInstruction(op_str.to_string()))
                 }
             })
         }
@@ -224,11 +468,11 @@ pub fn parse_instruction(line: &str, table: &HashMap<String, i32>, ins_count: us
         "lui" | "auipc" => {
             Ok(Instruction::Utype {
                 rd: parse_register(tokens.next())?, 
-                imm: parse_immediate(tokens.next(), false, true)?, 
+                imm: parse_immediate(tokens.next(), Field::U, table, pc, relocations)?, 
                 opcode: match op_str {
                     "lui" => 0b0110111,
                     "auipc" => 0b0010111,
-                    _ => return Err(AsmRiscVError::NotImplementedInstruction)
+                    _ => return Err(AsmRiscVError::UnknownInstruction(op_str.to_string()))
                 }
             })
         }
@@ -236,14 +480,14 @@ pub fn parse_instruction(line: &str, table: &HashMap<String, i32>, ins_count: us
         "jal" => {
             Ok(Instruction::Jtype {
                 rd: parse_register(tokens.next())?,
-                imm: parse_label_imm(tokens.next(), table, ins_count)?, 
+                imm: parse_label_imm(tokens.next(), table, pc, 21, relocations)?, 
                 opcode: 0b1101111 
             })
         },
 
         "jalr" => {
             let rd = parse_register(tokens.next())?;
-            let (imm, rs1) = parse_parenthesis(tokens.next())?;
+            let (imm, rs1) = parse_parenthesis(tokens.next(), Field::I, table, pc, relocations)?;
             Ok(Instruction::Itype { 
                 rd,
                 rs1,
@@ -260,7 +504,7 @@ pub fn parse_instruction(line: &str, table: &HashMap<String, i32>, ins_count: us
                 imm: match op_str {
                     "ecall" => 0,
                     "ebreak" => 1,
-                    _ => return Err(AsmRiscVError::SyntaxError)
+                    _ => return Err(AsmRiscVError::UnknownInstruction(op_str.to_string()))
                 }, 
                 opcode: 0b1110011,
                 funct3: 0b000, 
@@ -268,7 +512,7 @@ pub fn parse_instruction(line: &str, table: &HashMap<String, i32>, ins_count: us
         },
 
         _ => {
-            Err(AsmRiscVError::NotImplementedInstruction)
+            Err(AsmRiscVError::UnknownInstruction(op_str.to_string()))
         }
     }
 }
@@ -276,134 +520,115 @@ pub fn parse_instruction(line: &str, table: &HashMap<String, i32>, ins_count: us
 fn parse_register(reg_token: Option<&str>) -> Result<u32, AsmRiscVError> {
     let reg_str = match reg_token {
         Some(token_str) => token_str.trim(),
-        None => return Err(AsmRiscVError::SyntaxError)
+        None => return Err(AsmRiscVError::MissingOperand { expected: "a register" })
     };
 
-    if !reg_str.starts_with('x') {
-        return Err(AsmRiscVError::SyntaxError);
-    }
-   
-    match reg_str[1..].parse::<u32>() {
-        Ok(reg) => {
-            if reg > 31 {
-                Err(AsmRiscVError::NotExistRegister)
-            } else {
-                Ok(reg)
-            }
-        },
-        Err(_) => Err(AsmRiscVError::SyntaxError)
+    match register::GPR.lookup(reg_str) {
+        Lookup::Found(reg) => Ok(reg),
+        Lookup::OutOfRange => Err(AsmRiscVError::NoSuchRegister(reg_str.to_string())),
+        Lookup::NotRegister if reg_str.is_empty() => Err(AsmRiscVError::MissingOperand { expected: "a register" }),
+        Lookup::NotRegister => Err(AsmRiscVError::UnexpectedToken { found: reg_str.to_string(), expected: "a register" })
     }
 }
 
-fn parse_immediate(imm_token: Option<&str>, with_funct: bool, full_byte: bool) -> Result<i32, AsmRiscVError> {
+fn parse_immediate(imm_token: Option<&str>, field: Field, table: &SymbolTable, pc: Label, relocations: &mut Vec<Relocation>) -> Result<i32, AsmRiscVError> {
     let imm_str = match imm_token {
         Some(token_str) => token_str.trim(),
-        None => return Err(AsmRiscVError::SyntaxError)
+        None => return Err(AsmRiscVError::MissingOperand { expected: "an immediate" })
     };
 
-    let base = match imm_str.as_bytes() {
-        [b'0', b'x', ..] => 16,
-        [b'0', b'b', ..] => 2,
-        [b'0', b'o', ..] => 8,
-        _ => 10,
-    };
-    
-    let imm = if base != 10 {
-        match u32::from_str_radix(&imm_str[2..], base) {
-            Ok(raw) => {
-                if with_funct || full_byte {
-                    raw as i32
-                } else {
-                    (raw as i32) << 20 >> 20    
-                }   
-            }
-            Err(_) => return Err(AsmRiscVError::SyntaxError)
+    let expr = Expr::parse(imm_str)?;
+
+    // A relocation operator over a label leaves the field empty until addresses are known
+    if let Expr::Modifier(kind, operand) = &expr {
+        if field == Field::Shamt || kind.is_upper() != (field == Field::U) {
+            return Err(AsmRiscVError::MisplacedModifier { modifier: kind.name(), context: field.name() });
         }
-    } else {
-        match imm_str.trim().parse::<i32>() {
-            Ok(imm) => imm,
-            Err(_) => return Err(AsmRiscVError::SyntaxError)
+
+        let (target, addend) = operand.eval_target(table)?;
+        if target != Target::Absolute || matches!(kind, RelocKind::PcrelHi20 | RelocKind::PcrelLo12) {
+            relocations.push(Relocation { location: pc, kind: *kind, target, addend });
+            return Ok(0);
         }
-    };
+    }
+
+    let mut imm = expr.eval(table)?.absolute()?;
+
+    // A bare hex/binary/octal literal spells out the bit pattern of the field
+    let based_literal = imm_str.len() > 2 && matches!(&imm_str.as_bytes()[..2], [b'0', b'x' | b'X' | b'b' | b'B' | b'o' | b'O']);
+    if based_literal && matches!(field, Field::I | Field::S) && (0x800..=0xfff).contains(&imm) {
+        imm = imm << 52 >> 52;
+    }
 
-    if (full_byte && !(-2_i32.pow(19)..=2_i32.pow(19)-1).contains(&imm)) 
-        || (with_funct && !(0..=31).contains(&imm) 
-        || (!with_funct && !full_byte && !(-2048..=2047).contains(&imm))) {
-        Err(AsmRiscVError::ImmediateOverflow)
+    let (min, max) = field.range();
+    if !(min..=max).contains(&imm) {
+        Err(AsmRiscVError::ImmediateOutOfRange { value: imm, min, max, format: field.name() })
     } else {
-        Ok(imm)
+        Ok(imm as i32)
     }
 }
 
-fn parse_parenthesis(token: Option<&str>) -> Result<(i32, u32), AsmRiscVError> {
+const MEMORY_OPERAND: &str = "a memory operand `offset(reg)`";
+
+/// Parse a memory operand `offset(reg)`, where the offset is any expression and may be omitted
+fn parse_parenthesis(token: Option<&str>, field: Field, table: &SymbolTable, pc: Label, relocations: &mut Vec<Relocation>) -> Result<(i32, u32), AsmRiscVError> {
     let token_str = match token {
         Some(token_str) => token_str.trim(),
-        None => return Err(AsmRiscVError::SyntaxError)
+        None => return Err(AsmRiscVError::MissingOperand { expected: MEMORY_OPERAND })
     };
 
     let imm_str;
     let reg_str;
 
-    match token_str.split_once('(') {
+    match token_str.strip_suffix(')').and_then(|inner| inner.rsplit_once('(')) {
         Some((left, right)) => {
             imm_str = left.trim();
-            let clean_right = right.trim();
-            if !clean_right.starts_with('x') || !clean_right.ends_with(')') {
-                return Err(AsmRiscVError::SyntaxError);
-            }
-
-            reg_str = clean_right.trim_end_matches(')').trim();
+            reg_str = right.trim();
         },
 
-        None => return Err(AsmRiscVError::SyntaxError)
+        None => return Err(AsmRiscVError::UnexpectedToken { found: token_str.to_string(), expected: MEMORY_OPERAND })
     }
 
-    
-    Ok((match imm_str.trim().parse::<i32>() {
-        Ok(imm) => {
-            if !(-2048..=2047).contains(&imm) {
-                return Err(AsmRiscVError::ImmediateOverflow);
-            } else {
-                imm
-            }
-        },
-        Err(_) => return Err(AsmRiscVError::SyntaxError)
-    }, match reg_str[1..].parse::<u32>() {
-        Ok(reg) => {
-            if reg > 31 {
-                return Err(AsmRiscVError::NotExistRegister);
-            } else {
-                reg
-            }
-        },
-        Err(_) => return Err(AsmRiscVError::SyntaxError)
-    }))
+    let imm = if imm_str.is_empty() {
+        0
+    } else {
+        parse_immediate(Some(imm_str), field, table, pc, relocations)?
+    };
+
+    Ok((imm, parse_register(Some(reg_str))?))
+}
+
+/// Parse a branch/jump target into a byte offset from the current instruction.
+/// `bits` is the width of the signed offset field including the implicit zero bit.
+fn parse_label_imm(token: Option<&str>, table: &SymbolTable, pc: Label, bits: u32, relocations: &mut Vec<Relocation>) -> Result<i32, AsmRiscVError>{
+    let (kind, format) = if bits == 13 { (RelocKind::Branch, "B-type branch offset") } else { (RelocKind::Jal, "J-type jump offset") };
+    let imm = label_offset(token, table, pc, kind, relocations)?;
+    reloc::check_offset(imm, bits, format)?;
+    Ok(imm)
 }
 
-fn parse_label_imm(token: Option<&str>, table: &HashMap<String, i32>, ins_count: usize) -> Result<i32, AsmRiscVError>{
+/// Byte offset from `pc` to a target in the same section.
+/// A target that evaluates to a plain number is taken as the offset itself.
+/// Targets in other sections or files are recorded as a `kind` relocation and give 0.
+pub(super) fn label_offset(token: Option<&str>, table: &SymbolTable, pc: Label, kind: RelocKind, relocations: &mut Vec<Relocation>) -> Result<i32, AsmRiscVError> {
     let label_str = match token {
         Some(token_str) => token_str.trim(),
-        None => return Err(AsmRiscVError::SyntaxError)
+        None => return Err(AsmRiscVError::MissingOperand { expected: "a branch target" })
     };
 
-    let imm = match label_str.parse::<i32>() {
-        Ok(imm) => {      
-            imm >> 1 // For pc-relative addressing: pc = pc + offset * 2
-        },
-        Err(_) => {
-            let label = match table.get(&label_str.to_string()) {
-                Some(label) => label,
-                None => return Err(AsmRiscVError::SyntaxError)
-            };
-            // The `label - ins_count` is the line amount so need to * 2
-            // Also for pc-relative addressing: pc = pc + offset * 2
-            (*label - (ins_count as i32)) << 1
+    let offset = match Expr::parse(label_str)?.eval_target(table)? {
+        (Target::Absolute, offset) => offset,
+        (Target::Section(section), offset) if section == pc.section => offset - pc.offset as i64,
+        (target, addend) => {
+            relocations.push(Relocation { location: pc, kind, target, addend });
+            0
         }
     };
 
-    if !(-2_i32.pow(9)..2_i32.pow(9)-1).contains(&imm) {
-        Err(AsmRiscVError::ImmediateOverflow)
-    } else {
-        Ok(imm)
-    }
-}
\ No newline at end of file
+    i32::try_from(offset).map_err(|_| AsmRiscVError::ImmediateOutOfRange {
+        value: offset,
+        min: i32::MIN as i64,
+        max: i32::MAX as i64,
+        format: "32-bit offset",
+    })
+}
@@ -0,0 +1,159 @@
+use super::expr::{Expr, SymbolTable};
+use super::parser::{TokenError, at, label_offset, split_operands};
+use super::reloc::{RelocKind, Relocation};
+use super::section::Label;
+use crate::utils::exception::AsmRiscVError;
+
+/// Rewrite a pseudo-instruction into the real instructions it stands for.
+/// Returns `Ok(None)` when `op_str` is not a pseudo-instruction.
+/// Errors point at the operand they are about.
+pub(super) fn expand<'a>(op_str: &'a str, args_str: &'a str, table: &SymbolTable, pc: Label, relocations: &mut Vec<Relocation>) -> Result<Option<Vec<String>>, TokenError<'a>> {
+    let args = split_operands(args_str);
+
+    let expansion = match (op_str, args.as_slice()) {
+        ("nop", []) => vec!["addi x0, x0, 0".to_string()],
+        ("li", [rd, imm]) => {
+            let expr = Expr::parse(imm).map_err(at(imm))?;
+            let (hi, lo) = split_immediate(eval_immediate32(&expr, table).map_err(at(imm))?);
+            match (hi, lo) {
+                // Values depending on labels always take both instructions so the
+                // size decided in the first pass cannot change in the second
+                _ if expr.references_label(table) => {
+                    vec![format!("lui {}, {}", rd, hi), format!("addi {}, {}, {}", rd, rd, lo)]
+                },
+                (0, lo) => vec![format!("addi {}, x0, {}", rd, lo)],
+                (hi, 0) => vec![format!("lui {}, {}", rd, hi)],
+                (hi, lo) => vec![format!("lui {}, {}", rd, hi), format!("addi {}, {}, {}", rd, rd, lo)],
+            }
+        },
+        ("mv", [rd, rs]) => vec![format!("addi {}, {}, 0", rd, rs)],
+        ("not", [rd, rs]) => vec![format!("xori {}, {}, -1", rd, rs)],
+        ("neg", [rd, rs]) => vec![format!("sub {}, x0, {}", rd, rs)],
+        ("seqz", [rd, rs]) => vec![format!("sltiu {}, {}, 1", rd, rs)],
+        ("snez", [rd, rs]) => vec![format!("sltu {}, x0, {}", rd, rs)],
+        ("j", [target]) => vec![format!("jal x0, {}", target)],
+        ("jr", [rs]) => vec![format!("jalr x0, 0({})", rs)],
+        ("ret", []) => vec!["jalr x0, 0(x1)".to_string()],
+        ("call", [target]) | ("tail", [target]) => {
+            // auipc + jalr reach anywhere within +-2GiB of the auipc itself
+            let (hi, lo) = split_immediate(label_offset(Some(target), table, pc, RelocKind::Call, relocations).map_err(at(target))?);
+            let (link, scratch) = if op_str == "call" { ("x1", "x1") } else { ("x0", "x6") };
+            vec![format!("auipc {}, {}", scratch, hi), format!("jalr {}, {}({})", link, lo, scratch)]
+        },
+        ("beqz", [rs, target]) => vec![format!("beq {}, x0, {}", rs, target)],
+        ("bnez", [rs, target]) => vec![format!("bne {}, x0, {}", rs, target)],
+        ("bgt", [rs, rt, target]) => vec![format!("blt {}, {}, {}", rt, rs, target)],
+        ("ble", [rs, rt, target]) => vec![format!("bge {}, {}, {}", rt, rs, target)],
+        ("bgtu", [rs, rt, target]) => vec![format!("bltu {}, {}, {}", rt, rs, target)],
+        ("bleu", [rs, rt, target]) => vec![format!("bgeu {}, {}, {}", rt, rs, target)],
+
+        ("nop" | "ret", _) => return Err(operand_count(op_str, "")),
+        ("li", _) => return Err(operand_count(op_str, "rd, imm")),
+        ("mv" | "not" | "neg" | "seqz" | "snez", _) => return Err(operand_count(op_str, "rd, rs")),
+        ("j" | "call" | "tail", _) => return Err(operand_count(op_str, "target")),
+        ("jr", _) => return Err(operand_count(op_str, "rs")),
+        ("beqz" | "bnez", _) => return Err(operand_count(op_str, "rs, target")),
+        ("bgt" | "ble" | "bgtu" | "bleu", _) => return Err(operand_count(op_str, "rs, rt, target")),
+
+        _ => return Ok(None)
+    };
+
+    Ok(Some(expansion))
+}
+
+/// Wrong number of operands for a pseudo-instruction, pointing at its mnemonic
+fn operand_count<'a>(op_str: &'a str, expected: &'static str) -> TokenError<'a> {
+    at(op_str)(AsmRiscVError::OperandCount { mnemonic: op_str.to_string(), expected })
+}
+
+/// Number of real instructions a pseudo-instruction expands into,
+/// or `None` when `op_str` is not a pseudo-instruction.
+pub fn expanded_len(op_str: &str, args_str: &str, table: &SymbolTable) -> Option<usize> {
+    match op_str {
+        "li" => {
+            let expr = split_operands(args_str).get(1).map(|imm| Expr::parse(imm));
+            match expr {
+                Some(Ok(expr)) if expr.references_label(table) => Some(2),
+                Some(Ok(expr)) => match eval_immediate32(&expr, table).map(split_immediate) {
+                    Ok((0, _)) | Ok((_, 0)) | Err(_) => Some(1),
+                    Ok(_) => Some(2),
+                },
+                _ => Some(1)
+            }
+        },
+        "call" | "tail" => Some(2),
+        "nop" | "mv" | "not" | "neg" | "seqz" | "snez" | "j" | "jr" | "ret" |
+        "beqz" | "bnez" | "bgt" | "ble" | "bgtu" | "bleu" => Some(1),
+        _ => None
+    }
+}
+
+/// Accept anything that fits in 32 bits, either signed or unsigned
+fn eval_immediate32(expr: &Expr, table: &SymbolTable) -> Result<i32, AsmRiscVError> {
+    let imm = expr.eval(table)?.absolute()?;
+
+    if !(i32::MIN as i64..=u32::MAX as i64).contains(&imm) {
+        Err(AsmRiscVError::ImmediateOutOfRange { value: imm, min: i32::MIN as i64, max: u32::MAX as i64, format: "32-bit constant" })
+    } else {
+        Ok(imm as i32)
+    }
+}
+
+/// Split a 32-bit value into a signed 20-bit upper part and a signed 12-bit lower part
+/// such that `(hi << 12) + lo == imm`. The upper part is rounded by 0x800 because the
+/// lower part is sign-extended by the instruction that consumes it.
+pub(super) fn split_immediate(imm: i32) -> (i32, i32) {
+    let hi = imm.wrapping_add(0x800) >> 12;
+    let lo = imm.wrapping_sub(hi << 12);
+    (hi, lo)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{expand, expanded_len, split_immediate};
+    use crate::assembler::expr::SymbolTable;
+    use crate::assembler::section::{Label, Section};
+
+    fn li(imm: &str, table: &SymbolTable) -> Vec<String> {
+        let pc = Label { section: Section::Text, offset: 0 };
+        let expansion = match expand("li", &format!("a0, {}", imm), table, pc, &mut Vec::new()) {
+            Ok(Some(expansion)) => expansion,
+            _ => panic!("`li a0, {}` does not expand", imm)
+        };
+        assert_eq!(expanded_len("li", &format!("a0, {}", imm), table), Some(expansion.len()), "{}", imm);
+        expansion
+    }
+
+    #[test]
+    fn split_immediate_carries_into_the_upper_part() {
+        assert_eq!(split_immediate(0x7ff), (0, 0x7ff));
+        assert_eq!(split_immediate(0x800), (1, -0x800));
+        assert_eq!(split_immediate(0xfff), (1, -1));
+        assert_eq!(split_immediate(-0x800), (0, -0x800));
+        assert_eq!(split_immediate(-0x801), (-1, 0x7ff));
+        for imm in [0, 1, -1, 0x7ff, 0x800, 0x12345678, 0x7fffffff, i32::MIN, -0x12345678] {
+            let (hi, lo) = split_immediate(imm);
+            assert!((-0x800..0x800).contains(&lo), "{:#x}", imm);
+            assert_eq!((hi << 12).wrapping_add(lo), imm, "{:#x}", imm);
+        }
+    }
+
+    #[test]
+    fn li_uses_one_instruction_when_it_can() {
+        let table = SymbolTable::default();
+        assert_eq!(li("0x7ff", &table), ["addi a0, x0, 2047"]);
+        assert_eq!(li("-2048", &table), ["addi a0, x0, -2048"]);
+        assert_eq!(li("0xffffffff", &table), ["addi a0, x0, -1"]);
+        assert_eq!(li("0x1000", &table), ["lui a0, 1"]);
+        assert_eq!(li("0x800", &table), ["lui a0, 1", "addi a0, a0, -2048"]);
+        assert_eq!(li("0x12345fff", &table), ["lui a0, 74566", "addi a0, a0, -1"]);
+    }
+
+    #[test]
+    fn li_of_a_label_keeps_both_instructions() {
+        let mut table = SymbolTable::default();
+        table.labels.insert("start".to_string(), Label { section: Section::Text, offset: 0 });
+        table.labels.insert("end".to_string(), Label { section: Section::Text, offset: 8 });
+        assert_eq!(li("end - start", &table), ["lui a0, 0", "addi a0, a0, 8"]);
+    }
+}
@@ -0,0 +1,57 @@
+/// A register file the parser can resolve operand names against.
+///
+/// Every register can be written with its numeric name (`prefix` followed by
+/// the index) or with any of its ABI names. The first ABI name of each entry
+/// is the canonical one used when printing.
+pub struct RegisterFile {
+    pub prefix: char,
+    pub abi_names: [&'static [&'static str]; 32],
+}
+
+/// The integer register file `x0`..`x31`
+pub const GPR: RegisterFile = RegisterFile {
+    prefix: 'x',
+    abi_names: [
+        &["zero"], &["ra"], &["sp"], &["gp"], &["tp"],
+        &["t0"], &["t1"], &["t2"],
+        &["s0", "fp"], &["s1"],
+        &["a0"], &["a1"], &["a2"], &["a3"], &["a4"], &["a5"], &["a6"], &["a7"],
+        &["s2"], &["s3"], &["s4"], &["s5"], &["s6"], &["s7"], &["s8"], &["s9"], &["s10"], &["s11"],
+        &["t3"], &["t4"], &["t5"], &["t6"],
+    ],
+};
+
+/// Result of looking a name up in a register file
+pub enum Lookup {
+    Found(u32),
+    /// Numeric form with an index past the end of the file, e.g. `x32`
+    OutOfRange,
+    NotRegister,
+}
+
+impl RegisterFile {
+    /// Register called `name` in any case
+    pub fn lookup(&self, name: &str) -> Lookup {
+        let name = name.to_ascii_lowercase();
+        if let Some(index) = name.strip_prefix(self.prefix)
+            && !index.is_empty() && index.bytes().all(|b| b.is_ascii_digit()) {
+            return match index.parse::<u32>() {
+                Ok(reg) if (reg as usize) < self.abi_names.len() => Lookup::Found(reg),
+                _ => Lookup::OutOfRange,
+            };
+        }
+
+        match self.abi_names.iter().position(|names| names.contains(&name.as_str())) {
+            Some(reg) => Lookup::Found(reg as u32),
+            None => Lookup::NotRegister,
+        }
+    }
+
+    pub fn abi_name(&self, reg: u32) -> &'static str {
+        self.abi_names[reg as usize][0]
+    }
+
+    pub fn numeric_name(&self, reg: u32) -> String {
+        format!("{}{}", self.prefix, reg)
+    }
+}
@@ -0,0 +1,257 @@
+use super::pseudo::split_immediate;
+use super::section::{Label, Section};
+use crate::utils::exception::AsmRiscVError;
+
+/// Fields whose value depends on where sections or symbols end up
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RelocKind {
+    /// `%hi(sym)`: upper 20 bits of an absolute address, rounded for the `%lo` that follows
+    Hi20,
+    /// `%lo(sym)`: lower 12 bits of an absolute address in an I-type or S-type immediate
+    Lo12,
+    /// `%pcrel_hi(sym)`: upper 20 bits of `sym - pc`
+    PcrelHi20,
+    /// `%pcrel_lo(label)`: lower 12 bits of the `%pcrel_hi` at `label`
+    PcrelLo12,
+    /// B-type branch target
+    Branch,
+    /// J-type jump target
+    Jal,
+    /// `auipc` + `jalr` pair emitted by `call`/`tail`
+    Call,
+    /// 32-bit address stored by `.word`
+    Abs32,
+}
+
+impl RelocKind {
+    /// Operator named `name` without its `%`, in any case
+    pub fn from_name(name: &str) -> Option<RelocKind> {
+        match name.to_ascii_lowercase().as_str() {
+            "hi" => Some(RelocKind::Hi20),
+            "lo" => Some(RelocKind::Lo12),
+            "pcrel_hi" => Some(RelocKind::PcrelHi20),
+            "pcrel_lo" => Some(RelocKind::PcrelLo12),
+            _ => None
+        }
+    }
+
+    /// Operator spelling of the kinds written in source, a description of the others
+    pub fn name(&self) -> &'static str {
+        match self {
+            RelocKind::Hi20 => "%hi",
+            RelocKind::Lo12 => "%lo",
+            RelocKind::PcrelHi20 => "%pcrel_hi",
+            RelocKind::PcrelLo12 => "%pcrel_lo",
+            RelocKind::Branch => "branch offset",
+            RelocKind::Jal => "jump offset",
+            RelocKind::Call => "call offset",
+            RelocKind::Abs32 => "32-bit address",
+        }
+    }
+
+    /// Whether the operator fills a U-type immediate rather than a 12-bit one
+    pub fn is_upper(&self) -> bool {
+        matches!(self, RelocKind::Hi20 | RelocKind::PcrelHi20)
+    }
+
+    /// Whether the field holds a distance from the patched instruction
+    pub fn is_pc_relative(&self) -> bool {
+        matches!(self, RelocKind::PcrelHi20 | RelocKind::PcrelLo12 | RelocKind::Branch | RelocKind::Jal | RelocKind::Call)
+    }
+}
+
+/// What a relocation refers to; the relocation's addend is added to it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Target {
+    /// The addend alone is the address
+    Absolute,
+    /// Start of one of the file's own sections
+    Section(Section),
+    /// A named symbol, which may be defined in another file
+    Symbol(String),
+}
+
+/// A field that can only be filled in once addresses are known
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Relocation {
+    /// Instruction or data word to patch
+    pub location: Label,
+    pub kind: RelocKind,
+    /// For `PcrelLo12` this is the label on the matching `%pcrel_hi` instruction
+    pub target: Target,
+    pub addend: i64,
+}
+
+/// Compute the field value for `%hi`/`%lo` of an address that is already known
+pub fn resolve_absolute(kind: RelocKind, value: i64) -> Result<i32, AsmRiscVError> {
+    let (hi, lo) = split_immediate(value as i32);
+    match kind {
+        RelocKind::Hi20 => Ok(hi),
+        RelocKind::Lo12 => Ok(lo),
+        _ => Err(AsmRiscVError::MisplacedModifier { modifier: kind.name(), context: "value of a directive" }),
+    }
+}
+
+/// Fill in every relocation of `sections` given the address each section starts at.
+/// `resolve` supplies the address of symbols named by `Target::Symbol`.
+pub fn apply_all(sections: &mut [Vec<u8>; 4], relocations: &[Relocation], bases: &[u32; 4],
+                 resolve: &dyn Fn(&str) -> Option<u32>) -> Result<(), AsmRiscVError> {
+    let address = |target: &Target, addend: i64| -> Result<u32, AsmRiscVError> {
+        let base = match target {
+            Target::Absolute => 0,
+            Target::Section(section) => bases[section.index()],
+            Target::Symbol(name) => resolve(name).ok_or_else(|| AsmRiscVError::UndefinedSymbol(name.clone()))?,
+        };
+        Ok(base.wrapping_add(addend as u32))
+    };
+    let location = |label: &Label| bases[label.section.index()].wrapping_add(label.offset);
+
+    for reloc in relocations {
+        let pc = location(&reloc.location);
+        let target = address(&reloc.target, reloc.addend)?;
+        let value = match reloc.kind {
+            RelocKind::PcrelLo12 => {
+                let hi = relocations.iter()
+                                    .find(|hi| hi.kind == RelocKind::PcrelHi20 && location(&hi.location) == target)
+                                    .ok_or(AsmRiscVError::UnmatchedPcrelLo(target))?;
+                address(&hi.target, hi.addend)?.wrapping_sub(target)
+            },
+            kind if kind.is_pc_relative() => target.wrapping_sub(pc),
+            _ => target,
+        };
+
+        patch(&mut sections[reloc.location.section.index()], reloc.location.offset, reloc.kind, value)?;
+    }
+
+    Ok(())
+}
+
+/// Write the part of `value` selected by `kind` into the field at `offset`
+pub fn patch(section: &mut [u8], offset: u32, kind: RelocKind, value: u32) -> Result<(), AsmRiscVError> {
+    let offset = offset as usize;
+    let len = if kind == RelocKind::Call { 8 } else { 4 };
+    let bytes = section.get_mut(offset..offset + len).ok_or(AsmRiscVError::Internal("relocation past the end of its section"))?;
+    let ins = u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+    let (hi, lo) = split_immediate(value as i32);
+    let imm = value as i32;
+
+    let patched = match kind {
+        RelocKind::Abs32 => value,
+
+        // U-type: imm[31:12]
+        RelocKind::Hi20 | RelocKind::PcrelHi20 | RelocKind::Call => (ins & 0x00000fff) | ((hi as u32) << 12),
+
+        // S-type: imm[11:5] at 31:25, imm[4:0] at 11:7
+        RelocKind::Lo12 | RelocKind::PcrelLo12 if ins & 0x7f == 0b0100011 => {
+            (ins & 0x01fff07f) | (((lo as u32) & 0xfe0) << 20) | (((lo as u32) & 0x1f) << 7)
+        },
+
+        // I-type: imm[11:0] at 31:20
+        RelocKind::Lo12 | RelocKind::PcrelLo12 => (ins & 0x000fffff) | ((lo as u32) << 20),
+
+        // B-type: imm[12] | imm[10:5] | ... | imm[4:1] | imm[11]
+        RelocKind::Branch => {
+            check_offset(imm, 13, "B-type branch offset")?;
+            (ins & 0x01fff07f) | ((((imm & 0x1000) << 19) | ((imm & 0x07e0) << 20) | ((imm & 0x01e) << 7) | ((imm & 0x800) >> 4)) as u32)
+        },
+
+        // J-type: imm[20] | imm[10:1] | imm[11] | imm[19:12]
+        RelocKind::Jal => {
+            check_offset(imm, 21, "J-type jump offset")?;
+            (ins & 0x00000fff) | ((((imm & 0x100000) << 11) | ((imm & 0x0007fe) << 20) | ((imm & 0x000800) << 9) | (imm & 0x0ff000)) as u32)
+        },
+    };
+    bytes[..4].copy_from_slice(&patched.to_le_bytes());
+
+    // The `jalr` of a call pair takes the low part
+    if kind == RelocKind::Call {
+        let jalr = u32::from_le_bytes([bytes[4], bytes[5], bytes[6], bytes[7]]);
+        bytes[4..].copy_from_slice(&((jalr & 0x000fffff) | ((lo as u32) << 20)).to_le_bytes());
+    }
+
+    Ok(())
+}
+
+/// A branch or jump offset must be even and fit a signed field of `bits` bits
+pub fn check_offset(offset: i32, bits: u32, format: &'static str) -> Result<(), AsmRiscVError> {
+    let (min, max) = (-(1 << (bits - 1)), (1 << (bits - 1)) - 2);
+    if !(min..=max).contains(&offset) {
+        Err(AsmRiscVError::ImmediateOutOfRange { value: offset as i64, min: min as i64, max: max as i64, format })
+    } else if offset & 1 != 0 {
+        Err(AsmRiscVError::MisalignedOffset { offset: offset as i64, format })
+    } else {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{RelocKind, Relocation, Target, apply_all};
+    use crate::assembler::section::{Label, Section};
+    use crate::utils::exception::AsmRiscVError;
+
+    const AUIPC_A0: u32 = 0x00000517;
+    const LUI_A0: u32 = 0x00000537;
+    const ADDI_A0_A0: u32 = 0x00050513;
+    const SW_A1_A0: u32 = 0x00b52023;
+
+    fn text(words: &[u32]) -> [Vec<u8>; 4] {
+        let mut sections: [Vec<u8>; 4] = Default::default();
+        sections[Section::Text.index()] = words.iter().flat_map(|word| word.to_le_bytes()).collect();
+        sections
+    }
+
+    fn words(sections: &[Vec<u8>; 4]) -> Vec<u32> {
+        sections[Section::Text.index()].chunks(4).map(|word| u32::from_le_bytes(word.try_into().unwrap())).collect()
+    }
+
+    fn reloc(offset: u32, kind: RelocKind, target: Target, addend: i64) -> Relocation {
+        Relocation { location: Label { section: Section::Text, offset }, kind, target, addend }
+    }
+
+    #[test]
+    fn pcrel_lo_takes_the_low_part_of_its_anchor() {
+        let mut sections = text(&[AUIPC_A0, ADDI_A0_A0, SW_A1_A0]);
+        let relocations = [
+            reloc(0, RelocKind::PcrelHi20, Target::Section(Section::Data), 0),
+            reloc(4, RelocKind::PcrelLo12, Target::Section(Section::Text), 0),
+            reloc(8, RelocKind::PcrelLo12, Target::Section(Section::Text), 0),
+        ];
+        // `.data` is 0x800 past the `auipc`, so the upper part rounds up and the lower one is negative
+        let bases = [0x1000, 0, 0x1800, 0];
+        apply_all(&mut sections, &relocations, &bases, &|_| None).unwrap();
+        assert_eq!(words(&sections), [0x00001517, 0x80050513, 0x80b52023]);
+    }
+
+    #[test]
+    fn pcrel_lo_needs_a_pcrel_hi_at_its_label() {
+        let mut sections = text(&[AUIPC_A0, ADDI_A0_A0]);
+        let relocations = [
+            reloc(0, RelocKind::PcrelHi20, Target::Section(Section::Data), 0),
+            reloc(4, RelocKind::PcrelLo12, Target::Section(Section::Text), 4),
+        ];
+        let result = apply_all(&mut sections, &relocations, &[0x1000, 0, 0x2000, 0], &|_| None);
+        assert!(matches!(result, Err(AsmRiscVError::UnmatchedPcrelLo(0x1004))));
+    }
+
+    #[test]
+    fn hi_rounds_up_when_lo_is_negative() {
+        let mut sections = text(&[LUI_A0, ADDI_A0_A0, LUI_A0, ADDI_A0_A0]);
+        let relocations = [
+            reloc(0, RelocKind::Hi20, Target::Symbol("below".to_string()), 0),
+            reloc(4, RelocKind::Lo12, Target::Symbol("below".to_string()), 0),
+            reloc(8, RelocKind::Hi20, Target::Symbol("above".to_string()), 1),
+            reloc(12, RelocKind::Lo12, Target::Symbol("above".to_string()), 1),
+        ];
+        let resolve = |name: &str| match name {
+            "below" => Some(0x100007ff),
+            "above" => Some(0x12345ffe),
+            _ => None
+        };
+        apply_all(&mut sections, &relocations, &[0; 4], &resolve).unwrap();
+        assert_eq!(words(&sections), [0x10000537, 0x7ff50513, 0x12346537, 0xfff50513]);
+
+        let result = apply_all(&mut sections, &relocations, &[0; 4], &|_| None);
+        assert!(matches!(result, Err(AsmRiscVError::UndefinedSymbol(name)) if name == "below"));
+    }
+}
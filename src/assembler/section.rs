@@ -0,0 +1,53 @@
+use crate::utils::exception::AsmRiscVError;
+
+/// Output sections, in the order they are laid out in the image
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Section {
+    Text,
+    Rodata,
+    Data,
+    Bss,
+}
+
+impl Section {
+    pub const ALL: [Section; 4] = [Section::Text, Section::Rodata, Section::Data, Section::Bss];
+
+    /// Map a `.section` name onto one of the output sections.
+    /// Subsections such as `.text.startup` or `.rodata.str1.1` and the small-data
+    /// variants `.sdata`/`.sbss` fold into their parent section.
+    pub fn from_name(name: &str) -> Result<Section, AsmRiscVError> {
+        let name = name.trim().trim_matches('"');
+        let parent = match name.find('.') {
+            Some(0) => name[1..].split('.').next().unwrap_or(""),
+            _ => return Err(AsmRiscVError::UnknownSection(name.to_string()))
+        };
+
+        match parent.to_ascii_lowercase().as_str() {
+            "text" => Ok(Section::Text),
+            "rodata" | "srodata" => Ok(Section::Rodata),
+            "data" | "sdata" => Ok(Section::Data),
+            "bss" | "sbss" => Ok(Section::Bss),
+            _ => Err(AsmRiscVError::UnknownSection(name.to_string()))
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Section::Text => ".text",
+            Section::Rodata => ".rodata",
+            Section::Data => ".data",
+            Section::Bss => ".bss",
+        }
+    }
+
+    pub fn index(&self) -> usize {
+        *self as usize
+    }
+}
+
+/// Position of a label: byte offset from the start of its section
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Label {
+    pub section: Section,
+    pub offset: u32,
+}
@@ -0,0 +1,169 @@
+pub mod printer;
+
+use self::printer::PrintOptions;
+use crate::assembler::instruction::Instruction;
+use crate::utils::exception::AsmRiscVError;
+
+/// One word of disassembled machine code
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Line {
+    pub address: u32,
+    pub word: u32,
+    /// `None` when the word is not an instruction the assembler can produce
+    pub instruction: Option<Instruction>,
+}
+
+/// Turn a machine code word back into the instruction it encodes.
+/// Only encodings the assembler produces are accepted, so for every decoded
+/// word `decode(word)?.encode() == word` holds.
+pub fn decode(word: u32) -> Result<Instruction, AsmRiscVError> {
+    let opcode = word & 0x7f;
+    let rd = (word >> 7) & 0x1f;
+    let funct3 = (word >> 12) & 0x7;
+    let rs1 = (word >> 15) & 0x1f;
+    let rs2 = (word >> 20) & 0x1f;
+    let funct7 = word >> 25;
+
+    // Sign-extended immediates of each format
+    let i_imm = (word as i32) >> 20;
+    let s_imm = ((word as i32) >> 25 << 5) | ((word >> 7) & 0x1f) as i32;
+    let b_imm = ((word as i32) >> 31 << 12) | (((word >> 7) & 0x1) << 11) as i32 | (((word >> 25) & 0x3f) << 5) as i32 | (((word >> 8) & 0xf) << 1) as i32;
+    let j_imm = ((word as i32) >> 31 << 20) | (word & 0x000ff000) as i32 | (((word >> 20) & 0x1) << 11) as i32 | (((word >> 21) & 0x3ff) << 1) as i32;
+
+    let instruction = match opcode {
+        0b0110111 | 0b0010111 => Instruction::Utype { rd, imm: (word >> 12) as i32, opcode },
+        0b1101111 => Instruction::Jtype { rd, imm: j_imm, opcode },
+        0b1100011 => Instruction::Btype { rs1, rs2, imm: b_imm, opcode, funct3 },
+        0b0100011 => Instruction::Stype { rs1, rs2, imm: s_imm, opcode, funct3 },
+        0b0110011 => Instruction::Rtype { rd, rs1, rs2, opcode, funct3, funct7 },
+        // Shift amounts keep `funct7` above them, as the parser builds them
+        0b0010011 if funct3 == 0b001 || funct3 == 0b101 => Instruction::Itype { rd, rs1, imm: (word >> 20) as i32, opcode, funct3 },
+        0b0010011 | 0b0000011 | 0b1100111 | 0b1110011 => Instruction::Itype { rd, rs1, imm: i_imm, opcode, funct3 },
+        _ => return Err(AsmRiscVError::IllegalInstruction(word))
+    };
+
+    match instruction.mnemonic() {
+        Some(_) => Ok(instruction),
+        None => Err(AsmRiscVError::IllegalInstruction(word))
+    }
+}
+
+/// Decode little endian machine code placed at `base`, one line per word.
+/// A trailing partial word is zero extended and never decoded.
+pub fn disassemble(bytes: &[u8], base: u32) -> Vec<Line> {
+    bytes.chunks(4)
+         .enumerate()
+         .map(|(i, chunk)| {
+             let mut word = [0; 4];
+             word[..chunk.len()].copy_from_slice(chunk);
+             let word = u32::from_le_bytes(word);
+             let instruction = if chunk.len() == 4 { decode(word).ok() } else { None };
+             Line { address: base.wrapping_add(4 * i as u32), word, instruction }
+         })
+         .collect()
+}
+
+/// Address a branch or jump on `line` goes to
+pub fn target(line: &Line) -> Option<u32> {
+    match line.instruction? {
+        Instruction::Btype { imm, .. } | Instruction::Jtype { imm, .. } => Some(line.address.wrapping_add(imm as u32)),
+        _ => None
+    }
+}
+
+/// Render disassembled code like `objdump -d`: a header before every symbol,
+/// then address, raw word and instruction text on each line. `symbols` must be
+/// sorted by address. Branch targets are annotated with the symbol they fall in.
+pub fn render(lines: &[Line], symbols: &[(u32, String)], options: &PrintOptions) -> String {
+    let mut text = String::new();
+
+    for line in lines {
+        for (_, name) in symbols.iter().filter(|(address, _)| *address == line.address) {
+            text.push_str(&format!("\n{:08x} <{}>:\n", line.address, name));
+        }
+
+        let instruction = match line.instruction {
+            Some(instruction) => printer::print(&instruction, options),
+            None => format!(".word {:#010x}", line.word)
+        };
+        text.push_str(&format!("{:8x}:\t{:08x}\t{}", line.address, line.word, instruction));
+
+        if let Some(target) = target(line) {
+            text.push_str(&format!("  # {:#x}", target));
+            // The closest symbol at or before the target
+            if let Some((address, name)) = symbols.iter().rev().find(|(address, _)| *address <= target) {
+                match target - address {
+                    0 => text.push_str(&format!(" <{}>", name)),
+                    offset => text.push_str(&format!(" <{}+{:#x}>", name, offset))
+                }
+            }
+        }
+        text.push('\n');
+    }
+
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::decode;
+    use super::printer::{self, PrintOptions};
+    use crate::assembler;
+    use crate::assembler::section::Section;
+
+    /// One instance of every mnemonic, plus base instructions the printer writes as aliases
+    const INSTRUCTIONS: &[&str] = &[
+        "lui a0, 0x12345", "auipc t1, 0xfffff",
+        "jal ra, 2048", "jal zero, -4", "jalr ra, 12(a1)", "jalr zero, 0(ra)", "jalr zero, 0(t0)",
+        "beq a0, a1, -8", "bne s0, zero, 16", "blt t0, t1, 4094", "bge a2, a3, -4096", "bltu a4, a5, 8", "bgeu s1, s2, 2",
+        "lb a0, -1(sp)", "lh a1, 2(gp)", "lw a2, 2047(tp)", "lbu a3, -2048(s0)", "lhu a4, 0(a0)",
+        "sb a0, 1(sp)", "sh a1, -2(s1)", "sw ra, 12(sp)",
+        "addi a0, a1, -42", "addi a0, zero, 7", "addi t2, s3, 0", "addi zero, zero, 0",
+        "slti a0, a1, 5", "sltiu a0, a1, 1", "sltiu t0, t1, 9", "xori a0, a1, -1", "xori a0, a1, 0x7ff",
+        "ori s4, s5, 255", "andi s6, s7, 15", "slli a0, a0, 31", "srli t3, t4, 1", "srai t5, t6, 17",
+        "add a0, a1, a2", "sub a0, zero, a2", "sub s8, s9, s10", "sll a0, a1, a2", "slt a0, a1, a2",
+        "sltu a0, zero, a2", "sltu s11, a6, a7", "xor a0, a1, a2", "srl a0, a1, a2", "sra a0, a1, a2",
+        "or a0, a1, a2", "and a0, a1, a2",
+        "ecall", "ebreak",
+    ];
+
+    /// The word `text` assembles to at address 0
+    fn assemble(text: &str) -> u32 {
+        let (object, diagnostics) = assembler::assemble(text);
+        assert!(diagnostics.is_empty(), "`{}`: {:?}", text, diagnostics);
+        let bytes = &object.sections[Section::Text.index()];
+        assert_eq!(bytes.len(), 4, "`{}` is not one instruction", text);
+        u32::from_le_bytes(bytes[..].try_into().unwrap())
+    }
+
+    #[test]
+    fn decode_inverts_encode() {
+        for text in INSTRUCTIONS {
+            let word = assemble(text);
+            let instruction = decode(word).unwrap_or_else(|e| panic!("`{}`: {}", text, e));
+            assert_eq!(instruction.encode(), word, "`{}`", text);
+        }
+    }
+
+    #[test]
+    fn printed_instructions_reassemble() {
+        for text in INSTRUCTIONS {
+            let word = assemble(text);
+            let instruction = decode(word).unwrap();
+            for aliases in [false, true] {
+                for abi_names in [false, true] {
+                    let printed = printer::print(&instruction, &PrintOptions { aliases, abi_names });
+                    assert_eq!(assemble(&printed), word, "`{}` printed as `{}`", text, printed);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn rejects_encodings_the_assembler_never_produces() {
+        // `slli` with a nonzero `funct7`, an `ecall` with a destination and an all-zero word
+        for word in [0x0205_1513, 0x0000_00f3, 0x0000_0000] {
+            assert!(decode(word).is_err(), "{:#010x}", word);
+        }
+    }
+}
@@ -0,0 +1,78 @@
+use crate::assembler::instruction::Instruction;
+use crate::assembler::register;
+
+use std::fmt;
+
+/// How instructions are spelled out
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PrintOptions {
+    /// Print the pseudo-instruction a base instruction stands for, like `objdump` does
+    pub aliases: bool,
+    /// `a0` rather than `x10`
+    pub abi_names: bool,
+}
+
+impl Default for PrintOptions {
+    fn default() -> PrintOptions {
+        PrintOptions { aliases: true, abi_names: true }
+    }
+}
+
+/// Assembly text for `instruction` that the parser accepts.
+/// Branch and jump targets are written as offsets from the instruction.
+pub fn print(instruction: &Instruction, options: &PrintOptions) -> String {
+    let Some(mnemonic) = instruction.mnemonic() else {
+        return format!(".word {:#010x}", instruction.encode());
+    };
+    let reg = |index: u32| match options.abi_names {
+        true => register::GPR.abi_name(index).to_string(),
+        false => register::GPR.numeric_name(index),
+    };
+
+    if options.aliases && let Some(alias) = alias(instruction, &reg) {
+        return alias;
+    }
+
+    match *instruction {
+        Instruction::Itype {opcode: 0b1110011, ..} => mnemonic.to_string(),
+        Instruction::Itype {rd, rs1, imm, opcode: 0b0010011, funct3: 0b001 | 0b101} => {
+            format!("{} {}, {}, {}", mnemonic, reg(rd), reg(rs1), imm & 0x1f)
+        },
+        Instruction::Itype {rd, rs1, imm, opcode: 0b0010011, ..} => format!("{} {}, {}, {}", mnemonic, reg(rd), reg(rs1), imm),
+        Instruction::Itype {rd, rs1, imm, ..} => format!("{} {}, {}({})", mnemonic, reg(rd), imm, reg(rs1)),
+        Instruction::Rtype {rd, rs1, rs2, ..} => format!("{} {}, {}, {}", mnemonic, reg(rd), reg(rs1), reg(rs2)),
+        Instruction::Stype {rs1, rs2, imm, ..} => format!("{} {}, {}({})", mnemonic, reg(rs2), imm, reg(rs1)),
+        Instruction::Btype {rs1, rs2, imm, ..} => format!("{} {}, {}, {}", mnemonic, reg(rs1), reg(rs2), imm),
+        Instruction::Utype {rd, imm, ..} => format!("{} {}, {:#x}", mnemonic, reg(rd), imm & 0xfffff),
+        Instruction::Jtype {rd, imm, ..} => format!("{} {}, {}", mnemonic, reg(rd), imm),
+    }
+}
+
+/// Canonical assembly text the parser accepts, with ABI register names and no aliases.
+/// Branch and jump targets are written as offsets from the instruction.
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let options = PrintOptions { aliases: false, abi_names: true };
+        write!(f, "{}", print(self, &options))
+    }
+}
+
+/// The pseudo-instruction form of `instruction`, if it has one the parser accepts
+fn alias(instruction: &Instruction, reg: &dyn Fn(u32) -> String) -> Option<String> {
+    let text = match *instruction {
+        Instruction::Itype {rd: 0, rs1: 0, imm: 0, opcode: 0b0010011, funct3: 0b000} => "nop".to_string(),
+        Instruction::Itype {rd, rs1: 0, imm, opcode: 0b0010011, funct3: 0b000} => format!("li {}, {}", reg(rd), imm),
+        Instruction::Itype {rd, rs1, imm: 0, opcode: 0b0010011, funct3: 0b000} => format!("mv {}, {}", reg(rd), reg(rs1)),
+        Instruction::Itype {rd, rs1, imm: -1, opcode: 0b0010011, funct3: 0b100} => format!("not {}, {}", reg(rd), reg(rs1)),
+        Instruction::Itype {rd, rs1, imm: 1, opcode: 0b0010011, funct3: 0b011} => format!("seqz {}, {}", reg(rd), reg(rs1)),
+        Instruction::Rtype {rd, rs1: 0, rs2, opcode: 0b0110011, funct3: 0b000, funct7: 0b0100000} => format!("neg {}, {}", reg(rd), reg(rs2)),
+        Instruction::Rtype {rd, rs1: 0, rs2, opcode: 0b0110011, funct3: 0b011, funct7: 0b0000000} => format!("snez {}, {}", reg(rd), reg(rs2)),
+        Instruction::Itype {rd: 0, rs1: 1, imm: 0, opcode: 0b1100111, ..} => "ret".to_string(),
+        Instruction::Itype {rd: 0, rs1, imm: 0, opcode: 0b1100111, ..} => format!("jr {}", reg(rs1)),
+        Instruction::Jtype {rd: 0, imm, ..} => format!("j {}", imm),
+        Instruction::Btype {rs1, rs2: 0, imm, funct3: 0b000, ..} => format!("beqz {}, {}", reg(rs1), imm),
+        Instruction::Btype {rs1, rs2: 0, imm, funct3: 0b001, ..} => format!("bnez {}, {}", reg(rs1), imm),
+        _ => return None
+    };
+    Some(text)
+}
@@ -0,0 +1,284 @@
+pub mod debugger;
+pub mod gdb;
+pub mod memory;
+pub mod syscall;
+pub mod trace;
+
+use self::memory::Memory;
+use self::syscall::SyscallError;
+use crate::assembler::instruction::Instruction;
+use crate::assembler::register::GPR;
+use crate::disassembler;
+use crate::linker::Image;
+
+use thiserror::Error;
+
+/// One past the highest stack address; `sp` starts here
+pub const STACK_TOP: u32 = 0x7fff_f000;
+/// Bytes of stack mapped below `STACK_TOP`
+pub const STACK_SIZE: u32 = 0x0010_0000;
+
+/// Why an instruction could not complete
+#[derive(Error, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Fault {
+    #[error("illegal instruction {word:#010x}")]
+    IllegalInstruction { word: u32 },
+
+    #[error("instruction fetch from unmapped address {0:#010x}")]
+    FetchAccess(u32),
+
+    #[error("misaligned jump or branch target {0:#010x}")]
+    MisalignedFetch(u32),
+
+    #[error("load from unmapped address {0:#010x}")]
+    LoadAccess(u32),
+
+    #[error("misaligned load from {0:#010x}")]
+    MisalignedLoad(u32),
+
+    #[error("store to unmapped address {0:#010x}")]
+    StoreAccess(u32),
+
+    #[error("misaligned store to {0:#010x}")]
+    MisalignedStore(u32),
+}
+
+/// Why execution stopped. `pc` is left on the instruction that stopped it.
+#[derive(Error, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Stop {
+    #[error("ecall")]
+    Ecall,
+
+    #[error("ebreak")]
+    Ebreak,
+
+    #[error("{0}")]
+    Fault(Fault),
+
+    #[error("step limit reached")]
+    StepLimit,
+
+    /// The program asked to exit through a system call
+    #[error("exited with code {0}")]
+    Exit(i32),
+
+    #[error("{0}")]
+    Syscall(SyscallError),
+}
+
+/// A store as a commit log shows it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MemoryWrite {
+    pub address: u32,
+    /// Bytes written: 1, 2 or 4
+    pub width: u32,
+    /// Value stored, in its low `width` bytes
+    pub value: u32,
+}
+
+/// What one retired instruction did
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Commit {
+    pub pc: u32,
+    pub word: u32,
+    /// Destination register and the value written to it, even when it is `x0`
+    pub register: Option<(u32, u32)>,
+    /// Address a load read from
+    pub load: Option<u32>,
+    pub store: Option<MemoryWrite>,
+}
+
+/// An RV32I hart with its memory
+#[derive(Debug, Clone)]
+pub struct Machine {
+    pub pc: u32,
+    pub regs: [u32; 32],
+    pub memory: Memory,
+    /// Instructions retired so far
+    pub instret: u64,
+}
+
+impl Machine {
+    /// Load every section of `image`, map the stack and start at the image's entry
+    pub fn new(image: &Image) -> Machine {
+        let mut memory = Memory::default();
+        for output in &image.sections {
+            memory.load(output.address, &output.bytes);
+        }
+        memory.map(STACK_TOP - STACK_SIZE, STACK_SIZE);
+
+        let mut regs = [0; 32];
+        regs[2] = STACK_TOP;
+        Machine { pc: image.start(), regs, memory, instret: 0 }
+    }
+
+    pub fn reg(&self, index: u32) -> u32 {
+        self.regs[index as usize]
+    }
+
+    /// Writes to `x0` are discarded
+    pub fn set_reg(&mut self, index: u32, value: u32) {
+        if index != 0 {
+            self.regs[index as usize] = value;
+        }
+    }
+
+    /// `pc` and every register, four per line
+    pub fn dump_registers(&self) -> String {
+        let mut text = format!("pc  {:#010x}\n", self.pc);
+        for (i, value) in self.regs.iter().enumerate() {
+            let name = format!("{}/{}", GPR.numeric_name(i as u32), GPR.abi_name(i as u32));
+            text.push_str(&format!("{:<8} {:#010x}", name, value));
+            text.push(if i % 4 == 3 { '\n' } else { ' ' });
+        }
+        text
+    }
+
+    /// Instruction at `pc`
+    pub fn fetch(&self) -> Result<Instruction, Fault> {
+        if !self.pc.is_multiple_of(4) {
+            return Err(Fault::MisalignedFetch(self.pc));
+        }
+        let word = self.memory.read(self.pc, 4).ok_or(Fault::FetchAccess(self.pc))?;
+        disassembler::decode(word).map_err(|_| Fault::IllegalInstruction { word })
+    }
+
+    /// Execute the instruction at `pc` and report what it changed
+    pub fn step(&mut self) -> Result<Commit, Stop> {
+        let instruction = self.fetch().map_err(Stop::Fault)?;
+        let mnemonic = instruction.mnemonic().unwrap_or("");
+        let mut next_pc = self.pc.wrapping_add(4);
+        let mut commit = Commit { pc: self.pc, word: instruction.encode(), ..Commit::default() };
+
+        match instruction {
+            Instruction::Utype { rd, imm, .. } => {
+                let upper = (imm as u32) << 12;
+                let value = if mnemonic == "lui" { upper } else { self.pc.wrapping_add(upper) };
+                commit.register = Some((rd, value));
+            },
+
+            Instruction::Jtype { rd, imm, .. } => {
+                next_pc = self.jump_target(self.pc.wrapping_add(imm as u32))?;
+                commit.register = Some((rd, self.pc.wrapping_add(4)));
+            },
+
+            Instruction::Btype { rs1, rs2, imm, .. } => {
+                let (a, b) = (self.reg(rs1), self.reg(rs2));
+                let taken = match mnemonic {
+                    "beq" => a == b,
+                    "bne" => a != b,
+                    "blt" => (a as i32) < (b as i32),
+                    "bge" => (a as i32) >= (b as i32),
+                    "bltu" => a < b,
+                    _ => a >= b,
+                };
+                if taken {
+                    next_pc = self.jump_target(self.pc.wrapping_add(imm as u32))?;
+                }
+            },
+
+            Instruction::Itype { rd, rs1, imm, .. } => {
+                let a = self.reg(rs1);
+                let address = a.wrapping_add(imm as u32);
+                let shamt = (imm & 0x1f) as u32;
+                let value = match mnemonic {
+                    "ecall" => return Err(Stop::Ecall),
+                    "ebreak" => return Err(Stop::Ebreak),
+                    "jalr" => {
+                        next_pc = self.jump_target(address & !1)?;
+                        self.pc.wrapping_add(4)
+                    },
+                    "lb" => self.load(&mut commit, address, 1)? as i8 as i32 as u32,
+                    "lh" => self.load(&mut commit, address, 2)? as i16 as i32 as u32,
+                    "lw" => self.load(&mut commit, address, 4)?,
+                    "lbu" => self.load(&mut commit, address, 1)?,
+                    "lhu" => self.load(&mut commit, address, 2)?,
+                    "addi" => address,
+                    "slti" => ((a as i32) < imm) as u32,
+                    "sltiu" => (a < imm as u32) as u32,
+                    "xori" => a ^ imm as u32,
+                    "ori" => a | imm as u32,
+                    "andi" => a & imm as u32,
+                    "slli" => a << shamt,
+                    "srli" => a >> shamt,
+                    _ => ((a as i32) >> shamt) as u32,
+                };
+                commit.register = Some((rd, value));
+            },
+
+            Instruction::Stype { rs1, rs2, imm, .. } => {
+                let address = self.reg(rs1).wrapping_add(imm as u32);
+                let width = match mnemonic {
+                    "sb" => 1,
+                    "sh" => 2,
+                    _ => 4,
+                };
+                self.store(&mut commit, address, width, self.reg(rs2))?;
+            },
+
+            Instruction::Rtype { rd, rs1, rs2, .. } => {
+                let (a, b) = (self.reg(rs1), self.reg(rs2));
+                let value = match mnemonic {
+                    "add" => a.wrapping_add(b),
+                    "sub" => a.wrapping_sub(b),
+                    "sll" => a << (b & 0x1f),
+                    "slt" => ((a as i32) < (b as i32)) as u32,
+                    "sltu" => (a < b) as u32,
+                    "xor" => a ^ b,
+                    "srl" => a >> (b & 0x1f),
+                    "sra" => ((a as i32) >> (b & 0x1f)) as u32,
+                    "or" => a | b,
+                    _ => a & b,
+                };
+                commit.register = Some((rd, value));
+            },
+        }
+
+        if let Some((rd, value)) = commit.register {
+            self.set_reg(rd, value);
+        }
+        self.pc = next_pc;
+        self.instret += 1;
+        Ok(commit)
+    }
+
+    /// Step until something stops execution or `limit` instructions have run,
+    /// handing every retired instruction to `retired`
+    pub fn run(&mut self, limit: Option<u64>, mut retired: impl FnMut(&Commit)) -> Stop {
+        let mut steps = 0;
+        loop {
+            if limit.is_some_and(|limit| steps >= limit) {
+                return Stop::StepLimit;
+            }
+            match self.step() {
+                Ok(commit) => retired(&commit),
+                Err(stop) => return stop
+            }
+            steps += 1;
+        }
+    }
+
+    fn jump_target(&self, target: u32) -> Result<u32, Stop> {
+        match target.is_multiple_of(4) {
+            true => Ok(target),
+            false => Err(Stop::Fault(Fault::MisalignedFetch(target)))
+        }
+    }
+
+    fn load(&self, commit: &mut Commit, address: u32, width: u32) -> Result<u32, Stop> {
+        if !address.is_multiple_of(width) {
+            return Err(Stop::Fault(Fault::MisalignedLoad(address)));
+        }
+        commit.load = Some(address);
+        self.memory.read(address, width).ok_or(Stop::Fault(Fault::LoadAccess(address)))
+    }
+
+    fn store(&mut self, commit: &mut Commit, address: u32, width: u32, value: u32) -> Result<(), Stop> {
+        if !address.is_multiple_of(width) {
+            return Err(Stop::Fault(Fault::MisalignedStore(address)));
+        }
+        self.memory.write(address, width, value).ok_or(Stop::Fault(Fault::StoreAccess(address)))?;
+        commit.store = Some(MemoryWrite { address, width, value });
+        Ok(())
+    }
+}
@@ -0,0 +1,393 @@
+use super::syscall::Runtime;
+use super::{Machine, Stop};
+use crate::assembler::expr::{self, SymbolTable};
+use crate::assembler::instruction::Instruction;
+use crate::assembler::register::{GPR, Lookup};
+use crate::assembler::section::Section;
+use crate::disassembler::printer::{self, PrintOptions};
+use crate::linker::Image;
+use crate::output::listing::Unit;
+
+use std::collections::BTreeSet;
+use std::io::{self, BufRead, Write};
+
+const HELP: &str = "\
+break [label|address]   set a breakpoint, or list them without an argument
+delete [label|address]  remove a breakpoint, or all of them without an argument
+step [n]                execute n instructions, 1 by default
+next                    execute one instruction, running calls to completion
+continue                run until a breakpoint or the end of the program
+regs                    show every register
+print reg               show one register
+x address [bytes]       dump memory, 64 bytes by default and 4096 at most
+list                    show the source around the current line
+quit                    leave the debugger";
+
+/// Source lines around the current one shown by `list`
+const LIST_CONTEXT: usize = 3;
+
+/// Bytes `x` dumps when no length is given
+const DUMP_BYTES: u32 = 64;
+
+/// Most bytes one `x` dumps
+const MAX_DUMP_BYTES: u32 = 4096;
+
+/// Instruction bytes produced by one source line
+struct SourceLine {
+    address: u32,
+    size: u32,
+    unit: usize,
+    line: usize,
+}
+
+/// Interactive debugger for a linked program.
+/// Commands are read from the runtime's input, the same stream the program reads from.
+pub struct Debugger<'a, R: BufRead, W: Write> {
+    machine: Machine,
+    runtime: Runtime<R, W>,
+    image: &'a Image,
+    units: &'a [Unit<'a>],
+    lines: Vec<SourceLine>,
+    breakpoints: BTreeSet<u32>,
+    /// Why the program can't run any more, once it can't
+    finished: Option<Stop>,
+}
+
+impl<'a, R: BufRead, W: Write> Debugger<'a, R, W> {
+    /// `units` are the sources `image` was linked from, in link order
+    pub fn new(image: &'a Image, units: &'a [Unit<'a>], runtime: Runtime<R, W>) -> Self {
+        let mut lines: Vec<SourceLine> = units.iter()
+                                              .enumerate()
+                                              .flat_map(|(unit, source)| {
+                                                  let bases = image.unit_bases[unit];
+                                                  source.object.lines.iter()
+                                                        .filter(|info| info.instruction && info.size > 0)
+                                                        .map(move |info| SourceLine {
+                                                            address: bases[info.location.section.index()] + info.location.offset,
+                                                            size: info.size,
+                                                            unit,
+                                                            line: info.line,
+                                                        })
+                                              })
+                                              .collect();
+        lines.sort_by_key(|line| line.address);
+
+        Debugger {
+            machine: Machine::new(image),
+            runtime,
+            image,
+            units,
+            lines,
+            breakpoints: BTreeSet::new(),
+            finished: None,
+        }
+    }
+
+    /// Read and execute commands until `quit` or the end of input
+    pub fn run(&mut self) -> io::Result<()> {
+        self.show_location()?;
+        loop {
+            write!(self.runtime.output(), "(rvdb) ")?;
+            self.runtime.output().flush()?;
+
+            let mut line = String::new();
+            if self.runtime.input().read_line(&mut line)? == 0 {
+                writeln!(self.runtime.output())?;
+                return Ok(());
+            }
+            let words: Vec<&str> = line.split_whitespace().collect();
+            let Some((&command, args)) = words.split_first() else {
+                continue;
+            };
+
+            match command {
+                "b" | "break" => self.set_breakpoint(args)?,
+                "d" | "delete" => self.delete_breakpoint(args)?,
+                "s" | "step" | "si" | "stepi" => {
+                    let count = match args.first() {
+                        Some(count) => match count.parse() {
+                            Ok(count) => count,
+                            Err(_) => {
+                                writeln!(self.runtime.output(), "Invalid step count `{}`", count)?;
+                                continue;
+                            }
+                        },
+                        None => 1
+                    };
+                    self.resume(Some(count), None)?;
+                },
+                "n" | "next" | "ni" | "nexti" => self.next()?,
+                "c" | "continue" => self.resume(None, None)?,
+                "r" | "regs" => write!(self.runtime.output(), "{}", self.machine.dump_registers())?,
+                "p" | "print" => self.print_register(args)?,
+                "x" => self.dump_memory(args)?,
+                "l" | "list" => self.list()?,
+                "h" | "help" => writeln!(self.runtime.output(), "{}", HELP)?,
+                "q" | "quit" => return Ok(()),
+                _ => writeln!(self.runtime.output(), "Unknown command `{}`, try `help`", command)?
+            }
+        }
+    }
+
+    /// Address of a label of the program or of an absolute expression
+    fn address(&self, arg: &str) -> Option<u32> {
+        match self.image.symbol(arg) {
+            Some(symbol) => Some(symbol.address),
+            None => expr::eval_absolute(arg, &SymbolTable::default()).ok()
+                                                                    .and_then(|value| u32::try_from(value).ok())
+        }
+    }
+
+    fn set_breakpoint(&mut self, args: &[&str]) -> io::Result<()> {
+        let Some(arg) = args.first() else {
+            let described: Vec<String> = self.breakpoints.iter().map(|address| self.describe(*address)).collect();
+            for breakpoint in described {
+                writeln!(self.runtime.output(), "Breakpoint at {}", breakpoint)?;
+            }
+            return Ok(());
+        };
+        match self.address(arg) {
+            Some(address) => {
+                self.breakpoints.insert(address);
+                let breakpoint = self.describe(address);
+                writeln!(self.runtime.output(), "Breakpoint at {}", breakpoint)
+            },
+            None => writeln!(self.runtime.output(), "No label or address `{}`", arg)
+        }
+    }
+
+    fn delete_breakpoint(&mut self, args: &[&str]) -> io::Result<()> {
+        let Some(arg) = args.first() else {
+            self.breakpoints.clear();
+            return Ok(());
+        };
+        match self.address(arg) {
+            Some(address) if self.breakpoints.remove(&address) => Ok(()),
+            _ => writeln!(self.runtime.output(), "No breakpoint at `{}`", arg)
+        }
+    }
+
+    /// Step over the instruction at `pc`, letting a call it makes return first
+    fn next(&mut self) -> io::Result<()> {
+        let call = match self.machine.fetch() {
+            Ok(Instruction::Jtype { rd, .. }) => rd != 0,
+            Ok(instruction @ Instruction::Itype { rd, .. }) => rd != 0 && instruction.mnemonic() == Some("jalr"),
+            _ => false
+        };
+        match call {
+            true => self.resume(None, Some(self.machine.pc.wrapping_add(4))),
+            false => self.resume(Some(1), None)
+        }
+    }
+
+    /// Execute at most `count` instructions, stopping early at a breakpoint, when `pc`
+    /// comes back to `until` in the current frame or when the program stops
+    fn resume(&mut self, count: Option<u64>, until: Option<u32>) -> io::Result<()> {
+        if let Some(stop) = self.finished {
+            return writeln!(self.runtime.output(), "The program is not running: {}", stop);
+        }
+
+        let sp = self.machine.reg(2);
+        let mut executed = 0;
+        while count.is_none_or(|count| executed < count) {
+            if executed > 0 {
+                let pc = self.machine.pc;
+                if until == Some(pc) && self.machine.reg(2) >= sp {
+                    break;
+                }
+                if self.breakpoints.contains(&pc) {
+                    let breakpoint = self.describe(pc);
+                    writeln!(self.runtime.output(), "Breakpoint at {}", breakpoint)?;
+                    break;
+                }
+            }
+
+            let result = match self.machine.step() {
+                Err(Stop::Ecall) => match self.runtime.ecall(&mut self.machine) {
+                    Some(stop) => Err(stop),
+                    None => Ok(())
+                },
+                result => result.map(|_| ())
+            };
+            executed += 1;
+
+            match result {
+                Ok(()) => {},
+                // Like a breakpoint compiled into the program: report it and carry on after it
+                Err(Stop::Ebreak) => {
+                    self.machine.pc = self.machine.pc.wrapping_add(4);
+                    self.machine.instret += 1;
+                    writeln!(self.runtime.output(), "ebreak")?;
+                    break;
+                },
+                Err(stop) => {
+                    self.runtime.output().flush()?;
+                    self.finished = Some(stop);
+                    return writeln!(self.runtime.output(), "Program stopped at {:#010x}: {}", self.machine.pc, stop);
+                }
+            }
+        }
+        self.show_location()
+    }
+
+    /// Show the source line and instruction at `pc`
+    fn show_location(&mut self) -> io::Result<()> {
+        let pc = self.machine.pc;
+        if let Some(line) = self.source_line(pc) {
+            let (unit, number) = (&self.units[line.unit], line.line);
+            let text = unit.source.lines().nth(number - 1).unwrap_or("");
+            writeln!(self.runtime.output(), "{}:{}: {}", unit.name, number, text.trim())?;
+        }
+        let instruction = match self.machine.fetch() {
+            Ok(instruction) => printer::print(&instruction, &PrintOptions::default()),
+            Err(fault) => fault.to_string()
+        };
+        let location = self.describe(pc);
+        writeln!(self.runtime.output(), "=> {}: {}", location, instruction)
+    }
+
+    fn source_line(&self, address: u32) -> Option<&SourceLine> {
+        let index = self.lines.partition_point(|line| line.address <= address).checked_sub(1)?;
+        let line = &self.lines[index];
+        (address - line.address < line.size).then_some(line)
+    }
+
+    /// `address` followed by the nearest code label at or before it
+    fn describe(&self, address: u32) -> String {
+        let symbol = self.image.symbols.iter()
+                                       .filter(|symbol| symbol.section == Section::Text && symbol.address <= address)
+                                       .max_by_key(|symbol| symbol.address);
+        match symbol {
+            Some(symbol) if symbol.address == address => format!("{:#010x} <{}>", address, symbol.name),
+            Some(symbol) => format!("{:#010x} <{}+{:#x}>", address, symbol.name, address - symbol.address),
+            None => format!("{:#010x}", address)
+        }
+    }
+
+    fn print_register(&mut self, args: &[&str]) -> io::Result<()> {
+        let Some(&name) = args.first() else {
+            return writeln!(self.runtime.output(), "Missing register name");
+        };
+        let value = match (name, GPR.lookup(name)) {
+            ("pc", _) => self.machine.pc,
+            (_, Lookup::Found(reg)) => self.machine.reg(reg),
+            _ => return writeln!(self.runtime.output(), "No register `{}`", name)
+        };
+        writeln!(self.runtime.output(), "{} = {:#010x} ({})", name, value, value as i32)
+    }
+
+    /// Hex dump with 16 bytes per row; unmapped bytes show as `..`
+    fn dump_memory(&mut self, args: &[&str]) -> io::Result<()> {
+        let Some(start) = args.first().and_then(|arg| self.address(arg)) else {
+            return writeln!(self.runtime.output(), "Missing or invalid address");
+        };
+        let len = match args.get(1) {
+            Some(len) => match expr::eval_absolute(len, &SymbolTable::default()).ok().and_then(|len| u32::try_from(len).ok()) {
+                Some(len) => len,
+                None => return writeln!(self.runtime.output(), "Invalid length `{}`", len)
+            },
+            None => DUMP_BYTES
+        };
+        if len > MAX_DUMP_BYTES {
+            return writeln!(self.runtime.output(), "Length {} is more than the {} bytes `x` dumps at once", len, MAX_DUMP_BYTES);
+        }
+
+        for row in (0..len).step_by(16) {
+            let address = start.wrapping_add(row);
+            let bytes: Vec<String> = (0..16.min(len - row)).map(|i| match self.machine.memory.read_byte(address.wrapping_add(i)) {
+                                                                 Some(byte) => format!("{:02x}", byte),
+                                                                 None => "..".to_string()
+                                                             })
+                                                             .collect();
+            writeln!(self.runtime.output(), "{:08x}: {}", address, bytes.join(" "))?;
+        }
+        Ok(())
+    }
+
+    /// Print the lines around the current one, marking it
+    fn list(&mut self) -> io::Result<()> {
+        let Some(current) = self.source_line(self.machine.pc) else {
+            return writeln!(self.runtime.output(), "No source for {:#010x}", self.machine.pc);
+        };
+        let (units, unit, number) = (self.units, current.unit, current.line);
+        let first = number.saturating_sub(LIST_CONTEXT).max(1);
+        for (i, text) in units[unit].source.lines().enumerate().skip(first - 1).take(2 * LIST_CONTEXT + 1) {
+            let marker = if i + 1 == number { "=>" } else { "  " };
+            writeln!(self.runtime.output(), "{} {:>4} {}", marker, i + 1, text)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Debugger;
+    use crate::assembler;
+    use crate::emulator::syscall::Runtime;
+    use crate::linker::{self, LinkOptions};
+    use crate::output::listing::Unit;
+
+    use std::io::Cursor;
+
+    const PROGRAM: &str = "\
+_start:
+    li a0, 7
+    call show
+    li a0, 0
+    li a7, 93
+    ecall
+show:
+    li a7, 1
+    ecall
+    ret
+.data
+value: .word 0x12345678
+";
+
+    /// Everything the debugger writes while running `script` over `PROGRAM`
+    fn transcript(script: &str) -> String {
+        let (object, diagnostics) = assembler::assemble(PROGRAM);
+        assert!(diagnostics.is_empty(), "{:?}", diagnostics);
+        let image = linker::link(std::slice::from_ref(&object), &LinkOptions::default()).unwrap();
+        let units = [Unit { name: "test.s", source: PROGRAM, object: &object }];
+
+        let mut output = Vec::new();
+        let runtime = Runtime::new(&image, Cursor::new(script.as_bytes()), &mut output);
+        Debugger::new(&image, &units, runtime).run().unwrap();
+        String::from_utf8(output).unwrap()
+    }
+
+    #[test]
+    fn runs_a_script() {
+        let transcript = transcript("break show\ncontinue\nregs\nx value 6\nstep\ncontinue\nstep\nquit\nregs\n");
+        assert_eq!(transcript, "\
+test.s:2: li a0, 7
+=> 0x00000000 <_start>: li a0, 7
+(rvdb) Breakpoint at 0x00000018 <show>
+(rvdb) Breakpoint at 0x00000018 <show>
+test.s:8: li a7, 1
+=> 0x00000018 <show>: li a7, 1
+(rvdb) pc  0x00000018
+x0/zero  0x00000000 x1/ra    0x0000000c x2/sp    0x7ffff000 x3/gp    0x00000000
+x4/tp    0x00000000 x5/t0    0x00000000 x6/t1    0x00000000 x7/t2    0x00000000
+x8/s0    0x00000000 x9/s1    0x00000000 x10/a0   0x00000007 x11/a1   0x00000000
+x12/a2   0x00000000 x13/a3   0x00000000 x14/a4   0x00000000 x15/a5   0x00000000
+x16/a6   0x00000000 x17/a7   0x00000000 x18/s2   0x00000000 x19/s3   0x00000000
+x20/s4   0x00000000 x21/s5   0x00000000 x22/s6   0x00000000 x23/s7   0x00000000
+x24/s8   0x00000000 x25/s9   0x00000000 x26/s10  0x00000000 x27/s11  0x00000000
+x28/t3   0x00000000 x29/t4   0x00000000 x30/t5   0x00000000 x31/t6   0x00000000
+(rvdb) 00000024: 78 56 34 12 00 00
+(rvdb) test.s:9: ecall
+=> 0x0000001c <show+0x4>: ecall
+(rvdb) 7Program stopped at 0x00000014: exited with code 0
+(rvdb) The program is not running: exited with code 0
+(rvdb) ");
+    }
+
+    #[test]
+    fn bounds_memory_dumps() {
+        let transcript = transcript("x value 4000000000\nx value 4097\n");
+        assert_eq!(transcript.matches("is more than the 4096 bytes `x` dumps at once").count(), 2, "{}", transcript);
+        assert_eq!(transcript.lines().count(), 5, "{}", transcript);
+    }
+}
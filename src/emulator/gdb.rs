@@ -0,0 +1,422 @@
+pub mod client;
+
+use super::syscall::Runtime;
+use super::{Fault, Machine, Stop};
+use crate::assembler::register::GPR;
+
+use std::collections::BTreeSet;
+use std::io::{self, BufRead, BufReader, ErrorKind, Read, Write};
+use std::net::TcpStream;
+
+/// Instructions executed between checks for an interrupt from the debugger
+const POLL_INTERVAL: u64 = 4096;
+
+/// Registers in the order `g`/`G` transfer them: `x0`..`x31` then `pc`
+const REGISTER_COUNT: usize = 33;
+
+/// Signal numbers as GDB defines them, independent of the host
+const SIGINT: u8 = 2;
+const SIGILL: u8 = 4;
+const SIGTRAP: u8 = 5;
+const SIGBUS: u8 = 10;
+const SIGSEGV: u8 = 11;
+const SIGSYS: u8 = 12;
+
+/// Something the other end of a connection sent
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Received {
+    Packet(String),
+    /// A bare `0x03` byte asking a running target to stop
+    Interrupt,
+}
+
+/// One end of a remote serial protocol connection: `$data#checksum` framing,
+/// `+`/`-` acknowledgements until no-ack mode is negotiated, and `}` escaping
+pub struct Connection {
+    reader: BufReader<TcpStream>,
+    acks: bool,
+}
+
+impl Connection {
+    pub fn new(stream: TcpStream) -> Self {
+        Connection { reader: BufReader::new(stream), acks: true }
+    }
+
+    /// Stop sending and expecting `+`/`-` after every packet, as `QStartNoAckMode` asks
+    pub fn disable_acks(&mut self) {
+        self.acks = false;
+    }
+
+    /// Send one packet, resending it for as long as the other end rejects it
+    pub fn send(&mut self, data: &str) -> io::Result<()> {
+        let mut body = Vec::with_capacity(data.len());
+        for byte in data.bytes() {
+            match byte {
+                b'$' | b'#' | b'}' | b'*' => body.extend([b'}', byte ^ 0x20]),
+                _ => body.push(byte)
+            }
+        }
+        let checksum = body.iter().fold(0u8, |sum, byte| sum.wrapping_add(*byte));
+        let mut packet = vec![b'$'];
+        packet.extend(&body);
+        packet.extend(format!("#{:02x}", checksum).bytes());
+
+        loop {
+            self.reader.get_mut().write_all(&packet)?;
+            if !self.acks {
+                return Ok(());
+            }
+            // An interrupt can cross the reply on the wire; it no longer applies
+            loop {
+                match self.read_byte()? {
+                    b'+' => return Ok(()),
+                    b'-' => break,
+                    _ => continue
+                }
+            }
+        }
+    }
+
+    /// Next packet or interrupt, or `None` once the connection is closed
+    pub fn receive(&mut self) -> io::Result<Option<Received>> {
+        loop {
+            let byte = match self.read_byte() {
+                Ok(byte) => byte,
+                Err(e) if e.kind() == ErrorKind::UnexpectedEof => return Ok(None),
+                Err(e) => return Err(e)
+            };
+            match byte {
+                0x03 => return Ok(Some(Received::Interrupt)),
+                b'$' => {},
+                // Stray acknowledgements and line noise between packets
+                _ => continue
+            }
+
+            let mut body = Vec::new();
+            self.reader.read_until(b'#', &mut body)?;
+            if body.pop() != Some(b'#') {
+                return Ok(None);
+            }
+            let mut checksum = [0; 2];
+            self.reader.read_exact(&mut checksum)?;
+
+            let valid = std::str::from_utf8(&checksum).ok()
+                                                      .and_then(|checksum| u8::from_str_radix(checksum, 16).ok())
+                                                      == Some(body.iter().fold(0u8, |sum, byte| sum.wrapping_add(*byte)));
+            if self.acks {
+                self.reader.get_mut().write_all(if valid { b"+" } else { b"-" })?;
+            }
+            if !valid {
+                continue;
+            }
+
+            let mut data = Vec::with_capacity(body.len());
+            let mut bytes = body.into_iter();
+            while let Some(byte) = bytes.next() {
+                match byte {
+                    b'}' => data.push(bytes.next().unwrap_or(0) ^ 0x20),
+                    _ => data.push(byte)
+                }
+            }
+            return Ok(Some(Received::Packet(String::from_utf8_lossy(&data).into_owned())));
+        }
+    }
+
+    /// Whether an interrupt arrived, without waiting for one
+    pub fn interrupted(&mut self) -> io::Result<bool> {
+        if self.reader.buffer().is_empty() {
+            self.reader.get_ref().set_nonblocking(true)?;
+            let result = self.reader.fill_buf().map(|buffer| buffer.len());
+            self.reader.get_ref().set_nonblocking(false)?;
+            match result {
+                Ok(_) => {},
+                Err(e) if e.kind() == ErrorKind::WouldBlock => return Ok(false),
+                Err(e) => return Err(e)
+            }
+        }
+        match self.reader.buffer().first() {
+            Some(0x03) => {
+                self.reader.consume(1);
+                Ok(true)
+            },
+            _ => Ok(false)
+        }
+    }
+
+    fn read_byte(&mut self) -> io::Result<u8> {
+        let mut byte = [0];
+        self.reader.read_exact(&mut byte)?;
+        Ok(byte[0])
+    }
+}
+
+/// Target description telling GDB this is an RV32I hart and naming its registers
+fn target_xml() -> String {
+    let mut xml = String::from("<?xml version=\"1.0\"?>\n<!DOCTYPE target SYSTEM \"gdb-target.dtd\">\n\
+                                <target version=\"1.0\">\n<architecture>riscv:rv32</architecture>\n\
+                                <feature name=\"org.gnu.gdb.riscv.cpu\">\n");
+    for reg in 0..32 {
+        let kind = match reg {
+            1 => "code_ptr",
+            2 | 8 => "data_ptr",
+            _ => "int"
+        };
+        xml.push_str(&format!("<reg name=\"{}\" bitsize=\"32\" type=\"{}\" regnum=\"{}\"/>\n", GPR.abi_name(reg), kind, reg));
+    }
+    xml.push_str("<reg name=\"pc\" bitsize=\"32\" type=\"code_ptr\" regnum=\"32\"/>\n</feature>\n</target>\n");
+    xml
+}
+
+fn hex_bytes(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+fn parse_hex_bytes(text: &str) -> Option<Vec<u8>> {
+    if !text.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..text.len()).step_by(2).map(|i| u8::from_str_radix(text.get(i..i + 2)?, 16).ok()).collect()
+}
+
+fn parse_hex(text: &str) -> Option<u32> {
+    u32::from_str_radix(text, 16).ok()
+}
+
+/// `addr,len` as memory packets write it
+fn parse_range(text: &str) -> Option<(u32, u32)> {
+    let (address, len) = text.split_once(',')?;
+    Some((parse_hex(address)?, parse_hex(len)?))
+}
+
+/// A program run on behalf of a remote debugger.
+/// Breakpoints are kept aside instead of being patched into memory.
+pub struct Target<R: BufRead, W: Write> {
+    machine: Machine,
+    runtime: Runtime<R, W>,
+    breakpoints: BTreeSet<u32>,
+    /// Exit code once the program has exited
+    exited: Option<i32>,
+}
+
+impl<R: BufRead, W: Write> Target<R, W> {
+    pub fn new(machine: Machine, runtime: Runtime<R, W>) -> Self {
+        Target { machine, runtime, breakpoints: BTreeSet::new(), exited: None }
+    }
+
+    /// Answer requests on `connection` until the debugger kills the target, detaches or hangs up
+    pub fn serve(&mut self, connection: &mut Connection) -> io::Result<()> {
+        while let Some(received) = connection.receive()? {
+            let Received::Packet(packet) = received else {
+                // Nothing is running, so there is nothing to interrupt
+                continue;
+            };
+
+            // Packets come off the wire, so the first character may be any number of bytes long
+            let mut chars = packet.chars();
+            let command = chars.next();
+            let reply = match (command, chars.as_str()) {
+                (Some('?'), _) => self.stop_reply(SIGTRAP),
+                (Some('g'), _) => self.read_registers(),
+                (Some('G'), registers) => self.write_registers(registers),
+                (Some('p'), reg) => self.read_register(reg),
+                (Some('P'), assignment) => self.write_register(assignment),
+                (Some('m'), range) => self.read_memory(range),
+                (Some('M'), write) => self.write_memory(write),
+                (Some('Z'), breakpoint) => self.breakpoint(breakpoint, true),
+                (Some('z'), breakpoint) => self.breakpoint(breakpoint, false),
+                (Some('s'), address) => self.resume(connection, address, true)?,
+                (Some('c'), address) => self.resume(connection, address, false)?,
+                (Some('H'), _) => "OK".to_string(),
+                (Some('k'), _) => return Ok(()),
+                (Some('D'), _) => {
+                    connection.send("OK")?;
+                    return Ok(());
+                },
+                _ => self.query(connection, &packet)
+            };
+            connection.send(&reply)?;
+        }
+        Ok(())
+    }
+
+    /// General queries; anything unknown gets the empty "unsupported" reply
+    fn query(&mut self, connection: &mut Connection, packet: &str) -> String {
+        if packet.starts_with("qSupported") {
+            return "PacketSize=4000;QStartNoAckMode+;qXfer:features:read+".to_string();
+        }
+        if let Some(request) = packet.strip_prefix("qXfer:features:read:target.xml:") {
+            let xml = target_xml();
+            let Some((offset, len)) = parse_range(request) else {
+                return "E01".to_string();
+            };
+            let start = (offset as usize).min(xml.len());
+            let end = xml.len().min(start + len as usize);
+            let more = if end < xml.len() { 'm' } else { 'l' };
+            return format!("{}{}", more, &xml[start..end]);
+        }
+        match packet {
+            "QStartNoAckMode" => {
+                // The acknowledgement for this packet was already sent
+                connection.disable_acks();
+                "OK".to_string()
+            },
+            "qAttached" => "1".to_string(),
+            "qC" => "QC1".to_string(),
+            "qfThreadInfo" => "m1".to_string(),
+            "qsThreadInfo" => "l".to_string(),
+            _ => String::new()
+        }
+    }
+
+    fn stop_reply(&self, signal: u8) -> String {
+        match self.exited {
+            Some(code) => format!("W{:02x}", code as u8),
+            None => format!("S{:02x}", signal)
+        }
+    }
+
+    fn register(&self, reg: usize) -> u32 {
+        match reg {
+            32 => self.machine.pc,
+            _ => self.machine.regs[reg]
+        }
+    }
+
+    fn set_register(&mut self, reg: usize, value: u32) {
+        match reg {
+            32 => self.machine.pc = value,
+            _ => self.machine.set_reg(reg as u32, value)
+        }
+    }
+
+    fn read_registers(&self) -> String {
+        (0..REGISTER_COUNT).map(|reg| hex_bytes(&self.register(reg).to_le_bytes())).collect()
+    }
+
+    fn write_registers(&mut self, registers: &str) -> String {
+        match parse_hex_bytes(registers) {
+            Some(bytes) if bytes.len() == 4 * REGISTER_COUNT => {
+                for (reg, value) in bytes.chunks_exact(4).enumerate() {
+                    self.set_register(reg, u32::from_le_bytes(value.try_into().unwrap()));
+                }
+                "OK".to_string()
+            },
+            _ => "E01".to_string()
+        }
+    }
+
+    fn read_register(&self, reg: &str) -> String {
+        match parse_hex(reg) {
+            Some(reg) if (reg as usize) < REGISTER_COUNT => hex_bytes(&self.register(reg as usize).to_le_bytes()),
+            _ => "E01".to_string()
+        }
+    }
+
+    fn write_register(&mut self, assignment: &str) -> String {
+        let parsed = assignment.split_once('=').and_then(|(reg, value)| {
+            let reg = parse_hex(reg).filter(|reg| (*reg as usize) < REGISTER_COUNT)?;
+            let value: [u8; 4] = parse_hex_bytes(value)?.try_into().ok()?;
+            Some((reg as usize, u32::from_le_bytes(value)))
+        });
+        match parsed {
+            Some((reg, value)) => {
+                self.set_register(reg, value);
+                "OK".to_string()
+            },
+            None => "E01".to_string()
+        }
+    }
+
+    /// Bytes up to the first unmapped one, or an error if the first is unmapped
+    fn read_memory(&self, range: &str) -> String {
+        let Some((address, len)) = parse_range(range) else {
+            return "E01".to_string();
+        };
+        let bytes: Vec<u8> = (0..len).map_while(|i| self.machine.memory.read_byte(address.wrapping_add(i))).collect();
+        match bytes.is_empty() && len > 0 {
+            true => "E14".to_string(),
+            false => hex_bytes(&bytes)
+        }
+    }
+
+    fn write_memory(&mut self, write: &str) -> String {
+        let parsed = write.split_once(':').and_then(|(range, data)| {
+            let (address, len) = parse_range(range)?;
+            let bytes = parse_hex_bytes(data).filter(|bytes| bytes.len() == len as usize)?;
+            Some((address, bytes))
+        });
+        let Some((address, bytes)) = parsed else {
+            return "E01".to_string();
+        };
+        if !(0..bytes.len() as u32).all(|i| self.machine.memory.is_mapped(address.wrapping_add(i))) {
+            return "E14".to_string();
+        }
+        for (i, byte) in bytes.iter().enumerate() {
+            self.machine.memory.write_byte(address.wrapping_add(i as u32), *byte);
+        }
+        "OK".to_string()
+    }
+
+    /// `Z0`/`z0` software breakpoints; other kinds are unsupported
+    fn breakpoint(&mut self, breakpoint: &str, insert: bool) -> String {
+        let mut fields = breakpoint.split(',');
+        let (Some("0"), Some(address)) = (fields.next(), fields.next().and_then(parse_hex)) else {
+            return String::new();
+        };
+        match insert {
+            true => self.breakpoints.insert(address),
+            false => self.breakpoints.remove(&address)
+        };
+        "OK".to_string()
+    }
+
+    /// Execute one instruction or run until something stops the program, and say why it stopped
+    fn resume(&mut self, connection: &mut Connection, address: &str, single_step: bool) -> io::Result<String> {
+        if self.exited.is_some() {
+            return Ok(self.stop_reply(SIGTRAP));
+        }
+        if let Some(address) = parse_hex(address) {
+            self.machine.pc = address;
+        }
+
+        let mut executed: u64 = 0;
+        let signal = loop {
+            if executed > 0 {
+                if single_step || self.breakpoints.contains(&self.machine.pc) {
+                    break SIGTRAP;
+                }
+                if executed.is_multiple_of(POLL_INTERVAL) && connection.interrupted()? {
+                    break SIGINT;
+                }
+            }
+
+            let result = match self.machine.step() {
+                Err(Stop::Ecall) => match self.runtime.ecall(&mut self.machine) {
+                    Some(stop) => Err(stop),
+                    None => Ok(())
+                },
+                result => result.map(|_| ())
+            };
+            executed += 1;
+
+            match result {
+                Ok(()) => {},
+                Err(Stop::Exit(code)) => {
+                    self.exited = Some(code);
+                    break SIGTRAP;
+                },
+                Err(Stop::Ebreak | Stop::StepLimit) => break SIGTRAP,
+                // A system call the runtime can not service stops at the `ecall`
+                Err(Stop::Ecall | Stop::Syscall(_)) => break SIGSYS,
+                Err(Stop::Fault(fault)) => break match fault {
+                    Fault::IllegalInstruction { .. } => SIGILL,
+                    Fault::FetchAccess(_) | Fault::LoadAccess(_) | Fault::StoreAccess(_) => SIGSEGV,
+                    Fault::MisalignedFetch(_) | Fault::MisalignedLoad(_) | Fault::MisalignedStore(_) => SIGBUS,
+                }
+            }
+        };
+
+        self.runtime.output().flush()?;
+        Ok(self.stop_reply(signal))
+    }
+}
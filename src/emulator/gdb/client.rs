@@ -0,0 +1,233 @@
+use super::{Connection, Received, parse_hex, parse_hex_bytes, hex_bytes};
+
+use std::io::{self, ErrorKind};
+use std::net::{TcpStream, ToSocketAddrs};
+
+/// Why a target reports it stopped
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StopReply {
+    /// Stopped with a GDB signal number, e.g. 5 for a trap
+    Signal(u8),
+    /// The program exited with this status
+    Exited(u8),
+}
+
+/// Minimal remote serial protocol client, enough to drive a target without GDB
+pub struct Client {
+    connection: Connection,
+}
+
+fn invalid(message: String) -> io::Error {
+    io::Error::new(ErrorKind::InvalidData, message)
+}
+
+impl Client {
+    pub fn connect(address: impl ToSocketAddrs) -> io::Result<Client> {
+        Ok(Client { connection: Connection::new(TcpStream::connect(address)?) })
+    }
+
+    /// Send a packet and wait for the reply
+    pub fn request(&mut self, packet: &str) -> io::Result<String> {
+        self.connection.send(packet)?;
+        loop {
+            match self.connection.receive()? {
+                Some(Received::Packet(reply)) => return Ok(reply),
+                Some(Received::Interrupt) => continue,
+                None => return Err(io::Error::from(ErrorKind::UnexpectedEof))
+            }
+        }
+    }
+
+    /// Send a request whose only successful reply is `OK`
+    fn expect_ok(&mut self, packet: &str) -> io::Result<()> {
+        match self.request(packet)?.as_str() {
+            "OK" => Ok(()),
+            reply => Err(invalid(format!("`{}` failed: `{}`", packet, reply)))
+        }
+    }
+
+    /// Stop exchanging acknowledgements, if the target supports it
+    pub fn start_no_ack_mode(&mut self) -> io::Result<()> {
+        self.expect_ok("QStartNoAckMode")?;
+        self.connection.disable_acks();
+        Ok(())
+    }
+
+    /// `x0`..`x31` followed by `pc`
+    pub fn read_registers(&mut self) -> io::Result<Vec<u32>> {
+        let reply = self.request("g")?;
+        let bytes = parse_hex_bytes(&reply).ok_or_else(|| invalid(format!("bad register reply `{}`", reply)))?;
+        Ok(bytes.chunks_exact(4).map(|value| u32::from_le_bytes(value.try_into().unwrap())).collect())
+    }
+
+    /// Register `reg` in `g` order, so 32 is `pc`
+    pub fn read_register(&mut self, reg: u32) -> io::Result<u32> {
+        let reply = self.request(&format!("p{:x}", reg))?;
+        parse_hex_bytes(&reply).and_then(|bytes| bytes.try_into().ok())
+                               .map(u32::from_le_bytes)
+                               .ok_or_else(|| invalid(format!("bad register reply `{}`", reply)))
+    }
+
+    pub fn write_register(&mut self, reg: u32, value: u32) -> io::Result<()> {
+        self.expect_ok(&format!("P{:x}={}", reg, hex_bytes(&value.to_le_bytes())))
+    }
+
+    pub fn read_memory(&mut self, address: u32, len: u32) -> io::Result<Vec<u8>> {
+        let reply = self.request(&format!("m{:x},{:x}", address, len))?;
+        // Error replies like `E14` have an odd length, so they never parse as bytes
+        parse_hex_bytes(&reply).ok_or_else(|| invalid(format!("reading {:#x} failed: `{}`", address, reply)))
+    }
+
+    pub fn write_memory(&mut self, address: u32, bytes: &[u8]) -> io::Result<()> {
+        self.expect_ok(&format!("M{:x},{:x}:{}", address, bytes.len(), hex_bytes(bytes)))
+    }
+
+    pub fn set_breakpoint(&mut self, address: u32) -> io::Result<()> {
+        self.expect_ok(&format!("Z0,{:x},4", address))
+    }
+
+    pub fn remove_breakpoint(&mut self, address: u32) -> io::Result<()> {
+        self.expect_ok(&format!("z0,{:x},4", address))
+    }
+
+    /// Why the target is stopped now
+    pub fn stop_reason(&mut self) -> io::Result<StopReply> {
+        let reply = self.request("?")?;
+        Self::parse_stop(&reply)
+    }
+
+    pub fn step(&mut self) -> io::Result<StopReply> {
+        let reply = self.request("s")?;
+        Self::parse_stop(&reply)
+    }
+
+    pub fn cont(&mut self) -> io::Result<StopReply> {
+        let reply = self.request("c")?;
+        Self::parse_stop(&reply)
+    }
+
+    /// End the session and let the target go
+    pub fn detach(&mut self) -> io::Result<()> {
+        self.expect_ok("D")
+    }
+
+    fn parse_stop(reply: &str) -> io::Result<StopReply> {
+        let value = reply.get(1..3).and_then(parse_hex).map(|value| value as u8);
+        match (reply.chars().next(), value) {
+            (Some('S' | 'T'), Some(signal)) => Ok(StopReply::Signal(signal)),
+            (Some('W'), Some(status)) => Ok(StopReply::Exited(status)),
+            _ => Err(invalid(format!("bad stop reply `{}`", reply)))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Client, StopReply};
+    use crate::assembler;
+    use crate::emulator::Machine;
+    use crate::emulator::gdb::{Connection, Target};
+    use crate::emulator::syscall::Runtime;
+    use crate::linker::{self, Image, LinkOptions};
+
+    use std::io::{self, Cursor};
+    use std::net::TcpListener;
+    use std::thread::{self, JoinHandle};
+
+    const PROGRAM: &str = "
+        _start:
+            li a0, 1
+            addi a0, a0, 2
+        loop:
+            addi a0, a0, 4
+            li a7, 93
+            ecall
+        .data
+        value: .word 0x12345678
+    ";
+
+    fn image(source: &str) -> Image {
+        let (object, diagnostics) = assembler::assemble(source);
+        assert!(diagnostics.is_empty(), "{:?}", diagnostics);
+        linker::link(&[object], &LinkOptions::default()).unwrap()
+    }
+
+    /// Serve `image` on a free local port and connect a client to it
+    fn connect(image: &Image) -> (Client, JoinHandle<io::Result<()>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        let image = image.clone();
+        let server = thread::spawn(move || {
+            let (stream, _) = listener.accept()?;
+            let runtime = Runtime::new(&image, Cursor::new(Vec::new()), Vec::new());
+            Target::new(Machine::new(&image), runtime).serve(&mut Connection::new(stream))
+        });
+        (Client::connect(address).unwrap(), server)
+    }
+
+    #[test]
+    fn drives_a_program_to_exit() {
+        let image = image(PROGRAM);
+        let start = image.symbol("_start").unwrap().address;
+        let main_loop = image.symbol("loop").unwrap().address;
+        let value = image.symbol("value").unwrap().address;
+        let (mut client, server) = connect(&image);
+
+        assert_eq!(client.stop_reason().unwrap(), StopReply::Signal(5));
+        let registers = client.read_registers().unwrap();
+        assert_eq!(registers.len(), 33);
+        assert_eq!(registers[32], start);
+        assert_eq!(client.read_register(32).unwrap(), start);
+
+        assert_eq!(client.read_memory(value, 4).unwrap(), [0x78, 0x56, 0x34, 0x12]);
+        client.write_memory(value, &[1, 2]).unwrap();
+        assert_eq!(client.read_memory(value, 4).unwrap(), [1, 2, 0x34, 0x12]);
+        assert!(client.read_memory(0xdead_0000, 4).is_err());
+
+        client.set_breakpoint(main_loop).unwrap();
+        assert_eq!(client.cont().unwrap(), StopReply::Signal(5));
+        assert_eq!(client.read_register(32).unwrap(), main_loop);
+        assert_eq!(client.read_register(10).unwrap(), 3);
+        client.remove_breakpoint(main_loop).unwrap();
+
+        assert_eq!(client.step().unwrap(), StopReply::Signal(5));
+        assert_eq!(client.read_register(32).unwrap(), main_loop + 4);
+        assert_eq!(client.read_register(10).unwrap(), 7);
+
+        client.start_no_ack_mode().unwrap();
+        client.write_register(10, 42).unwrap();
+        assert_eq!(client.cont().unwrap(), StopReply::Exited(42));
+        assert_eq!(client.stop_reason().unwrap(), StopReply::Exited(42));
+
+        client.detach().unwrap();
+        server.join().unwrap().unwrap();
+    }
+
+    #[test]
+    fn survives_an_unsupported_system_call() {
+        let image = image("li a7, 1234\necall\nli a0, 5\nli a7, 93\necall");
+        let (mut client, server) = connect(&image);
+
+        assert_eq!(client.cont().unwrap(), StopReply::Signal(12));
+        assert_eq!(client.read_register(32).unwrap(), image.start() + 4);
+        client.write_register(17, 11).unwrap();
+        assert_eq!(client.cont().unwrap(), StopReply::Exited(5));
+
+        client.detach().unwrap();
+        server.join().unwrap().unwrap();
+    }
+
+    #[test]
+    fn ignores_packets_it_does_not_understand() {
+        let image = image(PROGRAM);
+        let (mut client, server) = connect(&image);
+
+        for packet in ["é", "ém0,4", "\u{1f980}g", "qUnknown"] {
+            assert_eq!(client.request(packet).unwrap(), "", "`{}`", packet);
+        }
+        assert_eq!(client.read_register(32).unwrap(), image.start());
+
+        client.detach().unwrap();
+        server.join().unwrap().unwrap();
+    }
+}
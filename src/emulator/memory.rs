@@ -0,0 +1,71 @@
+use std::collections::HashMap;
+
+/// Granularity memory is mapped in
+pub const PAGE_SIZE: u32 = 4096;
+
+/// Sparse byte-addressable little-endian memory.
+/// Only mapped pages can be accessed; everything else faults.
+#[derive(Debug, Clone, Default)]
+pub struct Memory {
+    pages: HashMap<u32, Box<[u8]>>,
+}
+
+impl Memory {
+    /// Make `[start, start + len)` accessible, zero filled where it was not mapped before
+    pub fn map(&mut self, start: u32, len: u32) {
+        if len == 0 {
+            return;
+        }
+        let first = start / PAGE_SIZE;
+        let last = ((start as u64 + len as u64 - 1) / PAGE_SIZE as u64) as u32;
+        for page in first..=last {
+            self.pages.entry(page).or_insert_with(|| vec![0; PAGE_SIZE as usize].into_boxed_slice());
+        }
+    }
+
+    pub fn is_mapped(&self, address: u32) -> bool {
+        self.pages.contains_key(&(address / PAGE_SIZE))
+    }
+
+    /// Map and fill memory with `bytes` starting at `start`
+    pub fn load(&mut self, start: u32, bytes: &[u8]) {
+        self.map(start, bytes.len() as u32);
+        for (i, byte) in bytes.iter().enumerate() {
+            self.write_byte(start.wrapping_add(i as u32), *byte);
+        }
+    }
+
+    pub fn read_byte(&self, address: u32) -> Option<u8> {
+        self.pages.get(&(address / PAGE_SIZE)).map(|page| page[(address % PAGE_SIZE) as usize])
+    }
+
+    pub fn write_byte(&mut self, address: u32, value: u8) -> Option<()> {
+        let page = self.pages.get_mut(&(address / PAGE_SIZE))?;
+        page[(address % PAGE_SIZE) as usize] = value;
+        Some(())
+    }
+
+    /// Little endian value of `width` bytes, or `None` if any of them is not mapped
+    pub fn read(&self, address: u32, width: u32) -> Option<u32> {
+        let mut value = 0;
+        for i in (0..width).rev() {
+            value = (value << 8) | self.read_byte(address.wrapping_add(i))? as u32;
+        }
+        Some(value)
+    }
+
+    /// Store the low `width` bytes of `value`. Nothing is written unless every byte is mapped.
+    pub fn write(&mut self, address: u32, width: u32, value: u32) -> Option<()> {
+        if !(0..width).all(|i| self.is_mapped(address.wrapping_add(i))) {
+            return None;
+        }
+        for i in 0..width {
+            self.write_byte(address.wrapping_add(i), (value >> (8 * i)) as u8)?;
+        }
+        Some(())
+    }
+
+    pub fn read_bytes(&self, address: u32, len: u32) -> Option<Vec<u8>> {
+        (0..len).map(|i| self.read_byte(address.wrapping_add(i))).collect()
+    }
+}
@@ -0,0 +1,199 @@
+use super::memory::PAGE_SIZE;
+use super::{Commit, Machine, Stop};
+use crate::linker::Image;
+
+use std::io::{BufRead, ErrorKind, Write};
+
+use thiserror::Error;
+
+/// Longest string `PrintString` reads before giving up on finding its terminator
+const MAX_STRING: u32 = 1 << 20;
+
+/// Why a system call could not be serviced
+#[derive(Error, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SyscallError {
+    #[error("unsupported system call {0}")]
+    Unsupported(u32),
+
+    #[error("system call argument points to unmapped address {0:#010x}")]
+    BadAddress(u32),
+
+    #[error("invalid argument {0} to system call")]
+    InvalidArgument(i32),
+
+    #[error("input is not an integer")]
+    InvalidInput,
+
+    #[error("end of input")]
+    EndOfInput,
+
+    #[error("host I/O error: {0}")]
+    Io(ErrorKind),
+}
+
+impl From<std::io::Error> for SyscallError {
+    fn from(e: std::io::Error) -> Self {
+        SyscallError::Io(e.kind())
+    }
+}
+
+/// Services `ecall`s the way RARS does: `a7` holds the call number, arguments and
+/// results go through `a0`/`a1`, and console I/O is mapped to `input`/`output`.
+pub struct Runtime<R: BufRead, W: Write> {
+    input: R,
+    output: W,
+    /// Current end of the heap `Sbrk` grows
+    brk: u32,
+}
+
+impl<R: BufRead, W: Write> Runtime<R, W> {
+    /// The heap starts at the first page boundary after the program's last section
+    pub fn new(image: &Image, input: R, output: W) -> Self {
+        let end = image.sections.iter()
+                                .map(|output| output.address as u64 + output.bytes.len() as u64)
+                                .max()
+                                .unwrap_or(0);
+        let brk = end.next_multiple_of(PAGE_SIZE as u64).min(u32::MAX as u64 + 1 - PAGE_SIZE as u64) as u32;
+        Runtime { input, output, brk }
+    }
+
+    /// Stream the program reads its input from
+    pub fn input(&mut self) -> &mut R {
+        &mut self.input
+    }
+
+    /// Stream the program's output goes to
+    pub fn output(&mut self) -> &mut W {
+        &mut self.output
+    }
+
+    /// Run `machine`, servicing every `ecall`, until it exits or stops for another reason.
+    /// `limit` bounds the instructions executed, system calls included. Every instruction
+    /// retired is handed to `retired`, down to the `ecall` that exits.
+    pub fn run(&mut self, machine: &mut Machine, limit: Option<u64>, mut retired: impl FnMut(&Commit)) -> Stop {
+        let start = machine.instret;
+        let stop = loop {
+            let remaining = limit.map(|limit| limit.saturating_sub(machine.instret - start));
+            match machine.run(remaining, &mut retired) {
+                Stop::Ecall => {},
+                stop => break stop
+            }
+
+            // A system call commits no register write of its own
+            let commit = Commit { pc: machine.pc, word: machine.memory.read(machine.pc, 4).unwrap_or(0), ..Commit::default() };
+            match self.ecall(machine) {
+                None => retired(&commit),
+                Some(stop @ Stop::Exit(_)) => {
+                    retired(&commit);
+                    break stop;
+                },
+                Some(stop) => break stop
+            }
+        };
+
+        match self.output.flush() {
+            Ok(()) => stop,
+            Err(e) => Stop::Syscall(e.into())
+        }
+    }
+
+    /// Service the `ecall` at `pc` and move past it.
+    /// Returns why execution has to stop, if it does.
+    pub fn ecall(&mut self, machine: &mut Machine) -> Option<Stop> {
+        let a0 = machine.reg(10);
+        let result = match machine.reg(17) {
+            // PrintInt
+            1 => write!(self.output, "{}", a0 as i32).map_err(SyscallError::from),
+            // PrintString
+            4 => self.print_string(machine, a0),
+            // ReadInt
+            5 => self.read_int().map(|value| machine.set_reg(10, value as u32)),
+            // ReadString
+            8 => self.read_string(machine, a0, machine.reg(11)),
+            // Sbrk
+            9 => self.sbrk(machine, a0 as i32).map(|address| machine.set_reg(10, address)),
+            // Exit
+            10 => return Some(Stop::Exit(0)),
+            // PrintChar
+            11 => self.output.write_all(&[a0 as u8]).map_err(SyscallError::from),
+            // ReadChar
+            12 => self.read_char().map(|byte| machine.set_reg(10, byte as u32)),
+            // Exit2, and `exit` from the Linux ABI
+            17 | 93 => return Some(Stop::Exit(a0 as i32)),
+            // PrintIntHex
+            34 => write!(self.output, "{:#010x}", a0).map_err(SyscallError::from),
+            // PrintIntBinary
+            35 => write!(self.output, "{:032b}", a0).map_err(SyscallError::from),
+            // PrintIntUnsigned
+            36 => write!(self.output, "{}", a0).map_err(SyscallError::from),
+            number => Err(SyscallError::Unsupported(number))
+        };
+
+        match result {
+            Ok(()) => {
+                machine.pc = machine.pc.wrapping_add(4);
+                machine.instret += 1;
+                None
+            },
+            Err(e) => Some(Stop::Syscall(e))
+        }
+    }
+
+    fn print_string(&mut self, machine: &Machine, address: u32) -> Result<(), SyscallError> {
+        let mut bytes = Vec::new();
+        for i in 0..MAX_STRING {
+            let address = address.wrapping_add(i);
+            match machine.memory.read_byte(address).ok_or(SyscallError::BadAddress(address))? {
+                0 => break,
+                byte => bytes.push(byte)
+            }
+        }
+        Ok(self.output.write_all(&bytes)?)
+    }
+
+    /// Read a line of input, flushing pending output first so prompts show up
+    fn read_line(&mut self) -> Result<String, SyscallError> {
+        self.output.flush()?;
+        let mut line = String::new();
+        match self.input.read_line(&mut line)? {
+            0 => Err(SyscallError::EndOfInput),
+            _ => Ok(line)
+        }
+    }
+
+    fn read_int(&mut self) -> Result<i32, SyscallError> {
+        self.read_line()?.trim().parse().map_err(|_| SyscallError::InvalidInput)
+    }
+
+    /// Store at most `max - 1` bytes of a line, newline included, then a terminating zero
+    fn read_string(&mut self, machine: &mut Machine, address: u32, max: u32) -> Result<(), SyscallError> {
+        if max == 0 {
+            return Ok(());
+        }
+        let line = self.read_line()?;
+        let bytes = &line.as_bytes()[..line.len().min(max as usize - 1)];
+        for (i, byte) in bytes.iter().chain(&[0]).enumerate() {
+            let address = address.wrapping_add(i as u32);
+            machine.memory.write_byte(address, *byte).ok_or(SyscallError::BadAddress(address))?;
+        }
+        Ok(())
+    }
+
+    fn read_char(&mut self) -> Result<u8, SyscallError> {
+        self.output.flush()?;
+        let byte = *self.input.fill_buf()?.first().ok_or(SyscallError::EndOfInput)?;
+        self.input.consume(1);
+        Ok(byte)
+    }
+
+    /// Grow the heap by `amount` bytes and return the address of the new block
+    fn sbrk(&mut self, machine: &mut Machine, amount: i32) -> Result<u32, SyscallError> {
+        let block = self.brk;
+        let brk = u32::try_from(amount).ok()
+                                       .and_then(|amount| block.checked_add(amount))
+                                       .ok_or(SyscallError::InvalidArgument(amount))?;
+        machine.memory.map(block, brk - block);
+        self.brk = brk;
+        Ok(block)
+    }
+}
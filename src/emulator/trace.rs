@@ -0,0 +1,131 @@
+use super::Commit;
+use crate::disassembler;
+use crate::disassembler::printer::{self, PrintOptions};
+
+use std::io::{self, Write};
+
+use thiserror::Error;
+
+/// Privilege level Spike reports; everything here runs in machine mode
+const PRIVILEGE: u32 = 3;
+
+/// Why a commit log could not be written
+#[derive(Error, Debug)]
+#[error("can not write the commit log: {0}")]
+pub struct LogError(#[from] io::Error);
+
+/// Spike-style log of every instruction retired, written to `writer`.
+/// Writing stops at the first error, which `finish` reports.
+pub struct CommitLog<W: Write> {
+    writer: W,
+    error: Option<io::Error>,
+}
+
+impl<W: Write> CommitLog<W> {
+    pub fn new(writer: W) -> Self {
+        CommitLog { writer, error: None }
+    }
+
+    pub fn record(&mut self, commit: &Commit) {
+        if self.error.is_none() && let Err(e) = self.writer.write_all(spike(commit).as_bytes()) {
+            self.error = Some(e);
+        }
+    }
+
+    /// Flush the log, or say why it could not be written
+    pub fn finish(mut self) -> Result<(), LogError> {
+        match self.error.take() {
+            Some(e) => Err(e.into()),
+            None => Ok(self.writer.flush()?)
+        }
+    }
+}
+
+/// What `spike -l --log-commits` prints for one instruction of hart 0: the disassembly line,
+/// then the commit line with the register written and the memory read or written.
+/// Only the disassembly text differs from Spike's, as it comes from our own printer.
+pub fn spike(commit: &Commit) -> String {
+    let text = match disassembler::decode(commit.word) {
+        Ok(instruction) => printer::print(&instruction, &PrintOptions::default()),
+        Err(_) => "unknown".to_string()
+    };
+    // Spike pads the mnemonic to eight columns
+    let text = match text.split_once(' ') {
+        Some((mnemonic, operands)) => format!("{:<7} {}", mnemonic, operands),
+        None => text
+    };
+
+    let mut line = format!("core   0: 0x{:08x} (0x{:08x}) {}\n", commit.pc, commit.word, text);
+    line.push_str(&format!("core   0: {} 0x{:08x} (0x{:08x})", PRIVILEGE, commit.pc, commit.word));
+    if let Some((rd, value)) = commit.register {
+        line.push_str(&format!(" x{:<2} 0x{:08x}", rd, value));
+    }
+    if let Some(address) = commit.load {
+        line.push_str(&format!(" mem 0x{:08x}", address));
+    }
+    if let Some(store) = commit.store {
+        let digits = 2 * store.width as usize;
+        line.push_str(&format!(" mem 0x{:08x} 0x{:0digits$x}", store.address, store.value & (u32::MAX >> (32 - 8 * store.width))));
+    }
+    line.push('\n');
+    line
+}
+
+#[cfg(test)]
+mod tests {
+    use super::CommitLog;
+    use crate::assembler;
+    use crate::emulator::syscall::Runtime;
+    use crate::emulator::{Machine, Stop};
+    use crate::linker::{self, LinkOptions};
+
+    use std::io::{self, Cursor, Write};
+
+    /// Writer that fails every write, like a full disk
+    struct Full;
+
+    impl Write for Full {
+        fn write(&mut self, _: &[u8]) -> io::Result<usize> {
+            Err(io::Error::from(io::ErrorKind::StorageFull))
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    /// Run `source` to completion, logging to `log`
+    fn run<W: Write>(source: &str, log: &mut CommitLog<W>) -> Stop {
+        let (object, diagnostics) = assembler::assemble(source);
+        assert!(diagnostics.is_empty(), "{:?}", diagnostics);
+        let image = linker::link(&[object], &LinkOptions::default()).unwrap();
+        let mut runtime = Runtime::new(&image, Cursor::new(Vec::new()), Vec::new());
+        runtime.run(&mut Machine::new(&image), None, |commit| log.record(commit))
+    }
+
+    #[test]
+    fn logs_every_instruction_up_to_the_exit() {
+        let mut text = Vec::new();
+        let mut log = CommitLog::new(&mut text);
+        assert_eq!(run("li a0, 3\nsw a0, -4(sp)\nli a7, 93\necall", &mut log), Stop::Exit(3));
+        log.finish().unwrap();
+
+        assert_eq!(String::from_utf8(text).unwrap(), "\
+core   0: 0x00000000 (0x00300513) li      a0, 3
+core   0: 3 0x00000000 (0x00300513) x10 0x00000003
+core   0: 0x00000004 (0xfea12e23) sw      a0, -4(sp)
+core   0: 3 0x00000004 (0xfea12e23) mem 0x7fffeffc 0x00000003
+core   0: 0x00000008 (0x05d00893) li      a7, 93
+core   0: 3 0x00000008 (0x05d00893) x17 0x0000005d
+core   0: 0x0000000c (0x00000073) ecall
+core   0: 3 0x0000000c (0x00000073)
+");
+    }
+
+    #[test]
+    fn reports_write_errors() {
+        let mut log = CommitLog::new(Full);
+        assert_eq!(run("li a7, 10\necall", &mut log), Stop::Exit(0));
+        assert!(log.finish().is_err());
+    }
+}
@@ -1,2 +1,6 @@
 pub mod assembler;
+pub mod disassembler;
+pub mod emulator;
+pub mod linker;
+pub mod output;
 pub mod utils;
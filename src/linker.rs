@@ -0,0 +1,188 @@
+use crate::assembler::Object;
+use crate::assembler::reloc::{self, Target};
+use crate::assembler::section::{Label, Section};
+use crate::utils::exception::AsmRiscVError;
+
+use std::collections::HashMap;
+
+/// Where the linked program is placed and where it starts
+#[derive(Debug, Clone, Default)]
+pub struct LinkOptions {
+    /// Address of the first byte of `.text`
+    pub base: u32,
+    /// Symbol execution starts at. Defaults to `_start` when defined, otherwise the start of `.text`.
+    pub entry: Option<String>,
+}
+
+/// One output section of the linked program
+#[derive(Debug, Clone)]
+pub struct OutputSection {
+    pub section: Section,
+    pub address: u32,
+    pub alignment: u32,
+    /// `.bss` holds zeros
+    pub bytes: Vec<u8>,
+}
+
+#[derive(Debug, Clone)]
+pub struct LinkedSymbol {
+    pub name: String,
+    pub address: u32,
+    pub section: Section,
+    pub global: bool,
+}
+
+/// A fully resolved program
+#[derive(Debug, Clone)]
+pub struct Image {
+    /// Address of the entry symbol, when there is one
+    pub entry: Option<u32>,
+    /// In `Section::ALL` order
+    pub sections: Vec<OutputSection>,
+    pub symbols: Vec<LinkedSymbol>,
+    /// Address each unit's sections were placed at, indexed by unit then `Section::index`
+    pub unit_bases: Vec<[u32; 4]>,
+}
+
+impl Image {
+    /// Where execution starts: the entry symbol, otherwise the start of `.text`
+    pub fn start(&self) -> u32 {
+        self.entry.unwrap_or(self.sections[Section::Text.index()].address)
+    }
+
+    /// Sections whose contents have to be stored in the output file
+    pub fn loaded(&self) -> impl Iterator<Item = &OutputSection> {
+        self.sections.iter().filter(|output| output.section != Section::Bss && !output.bytes.is_empty())
+    }
+
+    /// Raw bytes from the start of `.text` to the end of `.data`, with gaps filled with zero
+    pub fn flat_binary(&self) -> Vec<u8> {
+        let loaded: Vec<&OutputSection> = self.loaded().collect();
+        let Some(start) = loaded.iter().map(|output| output.address).min() else {
+            return Vec::new();
+        };
+        let end = loaded.iter().map(|output| output.address + output.bytes.len() as u32).max().unwrap_or(start);
+
+        let mut binary = vec![0; (end - start) as usize];
+        for output in loaded {
+            let offset = (output.address - start) as usize;
+            binary[offset..offset + output.bytes.len()].copy_from_slice(&output.bytes);
+        }
+        binary
+    }
+
+    pub fn symbol(&self, name: &str) -> Option<&LinkedSymbol> {
+        self.symbols.iter().find(|symbol| symbol.name == name)
+    }
+}
+
+/// Combine compilation units into one image.
+/// Each unit's contribution to a section is placed after the previous unit's, and sections
+/// follow each other in `Section::ALL` order starting at `options.base`. References to
+/// symbols a unit does not define are resolved against the `.globl` symbols of all units.
+pub fn link(objects: &[Object], options: &LinkOptions) -> Result<Image, AsmRiscVError> {
+    // bases[unit][section]
+    let mut bases = vec![[0_u32; 4]; objects.len()];
+    let mut sections = Vec::new();
+    let mut next = options.base;
+    for section in Section::ALL {
+        let alignment = objects.iter().map(|object| object.alignment(section)).max().unwrap_or(4);
+        next = align(next, alignment)?;
+        let address = next;
+        for (unit, object) in objects.iter().enumerate() {
+            next = align(next, object.alignment(section))?;
+            bases[unit][section.index()] = next;
+            next = next.checked_add(object.section(section).len() as u32).ok_or(AsmRiscVError::AddressOverflow)?;
+        }
+        sections.push(OutputSection { section, address, alignment, bytes: vec![0; (next - address) as usize] });
+    }
+
+    let mut symbols = Vec::new();
+    let mut globals = HashMap::new();
+    for (unit, object) in objects.iter().enumerate() {
+        for (name, label) in &object.symbols.labels {
+            let global = object.globals.contains(name);
+            let address = bases[unit][label.section.index()] + label.offset;
+            if global && globals.insert(name.clone(), address).is_some() {
+                return Err(AsmRiscVError::DuplicateSymbol(name.clone()));
+            }
+            symbols.push(LinkedSymbol { name: name.clone(), address, section: label.section, global });
+        }
+    }
+    symbols.sort_by(|a, b| (a.address, &a.name).cmp(&(b.address, &b.name)));
+
+    for (unit, object) in objects.iter().enumerate() {
+        let mut contents = object.sections.clone();
+        let resolve = |name: &str| globals.get(name).copied();
+        reloc::apply_all(&mut contents, &object.relocations, &bases[unit], &resolve)?;
+
+        for section in Section::ALL {
+            let output = &mut sections[section.index()];
+            let offset = (bases[unit][section.index()] - output.address) as usize;
+            let bytes = &contents[section.index()];
+            output.bytes[offset..offset + bytes.len()].copy_from_slice(bytes);
+        }
+    }
+
+    let entry = match &options.entry {
+        Some(name) => Some(find_entry(name, &globals, &symbols)?),
+        None => globals.get("_start").copied()
+    };
+
+    Ok(Image { entry, sections, symbols, unit_bases: bases })
+}
+
+/// References to symbols that neither their own unit nor any unit's `.globl` symbols define,
+/// as the unit, the symbol and the location of the reference
+pub fn undefined_symbols(objects: &[Object]) -> Vec<(usize, &str, Label)> {
+    let defined = |name: &str| objects.iter().any(|object| object.globals.contains(name) && object.symbols.labels.contains_key(name));
+
+    objects.iter()
+           .enumerate()
+           .flat_map(|(unit, object)| object.relocations.iter().map(move |reloc| (unit, reloc)))
+           .filter_map(|(unit, reloc)| match &reloc.target {
+               Target::Symbol(name) if !defined(name) => Some((unit, name.as_str(), reloc.location)),
+               _ => None
+           })
+           .collect()
+}
+
+/// A global symbol, or a local one that only a single unit defines
+fn find_entry(name: &str, globals: &HashMap<String, u32>, symbols: &[LinkedSymbol]) -> Result<u32, AsmRiscVError> {
+    if let Some(address) = globals.get(name) {
+        return Ok(*address);
+    }
+
+    let mut locals = symbols.iter().filter(|symbol| symbol.name == name);
+    match (locals.next(), locals.next()) {
+        (Some(symbol), None) => Ok(symbol.address),
+        (Some(_), Some(_)) => Err(AsmRiscVError::DuplicateSymbol(name.to_string())),
+        (None, _) => Err(AsmRiscVError::UndefinedSymbol(name.to_string()))
+    }
+}
+
+fn align(address: u32, alignment: u32) -> Result<u32, AsmRiscVError> {
+    address.checked_next_multiple_of(alignment).ok_or(AsmRiscVError::AddressOverflow)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{LinkOptions, link};
+    use crate::assembler;
+
+    fn link_with_entry(source: &str, entry: &str) -> Result<Option<u32>, String> {
+        let (object, diagnostics) = assembler::assemble(source);
+        assert!(diagnostics.is_empty(), "{:?}", diagnostics);
+        let options = LinkOptions { entry: Some(entry.to_string()), ..LinkOptions::default() };
+        link(&[object], &options).map(|image| image.entry).map_err(|e| e.to_string())
+    }
+
+    #[test]
+    fn entry_matches_labels_as_spelled() {
+        let source = "nop\nMain:\nnop\n.globl Other\nOther:\nnop";
+        assert_eq!(link_with_entry(source, "Main"), Ok(Some(4)));
+        assert_eq!(link_with_entry(source, "Other"), Ok(Some(8)));
+        assert_eq!(link_with_entry(source, "main"), Err("undefined symbol `main`".to_string()));
+        assert_eq!(link_with_entry(source, "Missing"), Err("undefined symbol `Missing`".to_string()));
+    }
+}
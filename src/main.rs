@@ -1,74 +1,493 @@
-
-use risc_v_assembler::assembler::{self, parser};
-use risc_v_assembler::utils::{file, exception::AsmRiscVError};
+use risc_v_assembler::assembler::{self, Object, expr::{self, SymbolTable}};
+use risc_v_assembler::disassembler::{self, printer::PrintOptions};
+use risc_v_assembler::emulator::{Machine, Stop, debugger::Debugger, gdb::{Connection, Target}, syscall::Runtime, trace::CommitLog};
+use risc_v_assembler::linker::{self, Image, LinkOptions};
+use risc_v_assembler::output::{Format, WriteOptions, elf, listing, rom::{self, RomStyle}};
+use risc_v_assembler::utils::{diagnostic::{self, Diagnostic}, exception::AsmRiscVError, explain, file};
 
 use std::env;
-use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, BufWriter};
+use std::net::TcpListener;
 
-const USAGE: &str = "Usage: cargo run <asm_file> [asm_file] ...";
+const USAGE: &str = "Usage: cargo run [-c] [-l listing] [-f bin|elf|hex|srec|memh|memb|coe|mif|txt|sv|vhdl] [-o output] [--base addr] [--entry symbol] [--mem-addresses] [--rom-name name] [--rom-style case|array] [--address-width bits] [--word-size bits] <asm_file> [asm_file] ...
+       cargo run disasm [--base addr] [--no-aliases] [--numeric] <binary_or_elf_file>
+       cargo run run [--base addr] [--entry symbol] [--max-steps n] [--log-commits file] <asm_file> [asm_file] ...
+       cargo run debug [--base addr] [--entry symbol] <asm_file> [asm_file] ...
+       cargo run gdb [--base addr] [--entry symbol] [--port port] <asm_file> [asm_file] ...
+       cargo run --explain <code>";
 
-fn main() {
-    let mut instructions = Vec::new();
+/// Port `gdb` listens on unless told otherwise, the one QEMU's `-s` uses
+const DEFAULT_GDB_PORT: u16 = 1234;
 
-    let args = env::args().skip(1);
+struct Options {
+    format: Format,
+    output: Option<String>,
+    listing: Option<String>,
+    /// Write one relocatable object per input instead of linking them
+    compile_only: bool,
+    link: LinkOptions,
+    write: WriteOptions,
+    inputs: Vec<String>,
+}
 
-    if args.len() == 0 {
-        eprintln!("Error: No input file\n{}", USAGE);
-        std::process::exit(1);
-    }
+fn parse_args(mut args: impl Iterator<Item = String>) -> Result<Options, String> {
+    let mut options = Options {
+        format: Format::Binary,
+        output: None,
+        listing: None,
+        compile_only: false,
+        link: LinkOptions::default(),
+        write: WriteOptions::default(),
+        inputs: Vec::new(),
+    };
 
-    for arg in args {
-        match file::read_asm(&arg) {
-            Ok(content) => {
-                let mut label_table = HashMap::<String, i32>::new();
-                let content_iter = content.lines()
-                                          .map(|line| line.split_once('#').map(|(code, _)| code).unwrap_or(line))
-                                          .flat_map(|clean_line| clean_line.split(';'))
-                                          .map(|token| token.trim())
-                                          .filter(|token| !token.is_empty());
-                                        
-                for (i, line) in content_iter.clone().enumerate(){
-                    let ins_line_num = i - label_table.len();
-                    if let Err(e) = parser::parse_label(line, &mut label_table, ins_line_num) {     
-                        match e {
-                            AsmRiscVError::ParseEmptyLine => continue,
-                            _ => {
-                                eprintln!("{:?}", e);
-                                std::process::exit(1);
-                            }
-                        }
-                    }
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-f" | "--format" => {
+                let name = args.next().ok_or("Missing format name")?;
+                options.format = Format::from_name(&name).ok_or(format!("Unknown format `{}`", name))?;
+            },
+            "-o" | "--output" => {
+                options.output = Some(args.next().ok_or("Missing output file name")?);
+            },
+            "-c" => options.compile_only = true,
+            "-l" | "--listing" => {
+                options.listing = Some(args.next().ok_or("Missing listing file name")?);
+            },
+            "--base" => {
+                options.link.base = parse_address(args.next())?;
+            },
+            "--entry" => {
+                options.link.entry = Some(args.next().ok_or("Missing entry symbol")?);
+            },
+            "--mem-addresses" => options.write.address_markers = true,
+            "--rom-name" => {
+                options.write.rom_name = Some(args.next().ok_or("Missing ROM name")?);
+            },
+            "--rom-style" => {
+                let name = args.next().ok_or("Missing ROM style")?;
+                options.write.rom_style = RomStyle::from_name(&name).ok_or(format!("Unknown ROM style `{}`", name))?;
+            },
+            "--address-width" => {
+                let value = args.next().ok_or("Missing address width")?;
+                match value.parse() {
+                    Ok(width) if width > 0 => options.write.address_width = Some(width),
+                    _ => return Err(format!("Invalid address width `{}`", value))
                 }
-                
-                for line in content_iter {
-                    println!("{}", line);
-                    match parser::parse_instruction(line, &label_table, instructions.len()) {
-                        Ok(ins) => {
-                            println!("{:?}", ins);  
-                            instructions.push(ins);
-                        }
-                        Err(e) => {
-                            match e {
-                                AsmRiscVError::ParseEmptyLine => continue,
-                                _ => {
-                                    eprintln!("{:?}", e);
-                                    std::process::exit(1);
-                                }
-                            }
-                        }
-                    }
+            },
+            "--word-size" => {
+                let value = args.next().ok_or("Missing word size")?;
+                match value.parse() {
+                    Ok(size) if rom::WORD_SIZES.contains(&size) => options.write.word_size = Some(size),
+                    _ => return Err(format!("Invalid word size `{}`, expected 8, 16, 32 or 64", value))
                 }
+            },
+            _ if arg.starts_with('-') => return Err(format!("Unknown option `{}`", arg)),
+            _ => options.inputs.push(arg)
+        }
+    }
 
-                let binary_contents = assembler::assembly(&instructions);
-                println!("{:x?}\n{:?}", binary_contents, label_table);
-                if let Err(e) = file::write_binary(&arg, &binary_contents) {
-                    eprintln!("{:?}", e);
-                }
+    if options.inputs.is_empty() {
+        return Err("No input file".to_string());
+    }
+    if options.compile_only && options.output.is_some() && options.inputs.len() > 1 {
+        return Err("`-o` with `-c` needs a single input file".to_string());
+    }
+    let max_width = rom::max_address_width(options.format);
+    if let Some(width) = options.write.address_width && width > max_width {
+        return Err(format!("Address width {} is wider than the {} bits this format supports", width, max_width));
+    }
+
+    Ok(options)
+}
+
+struct DisasmOptions {
+    /// Address of the first byte of a raw binary
+    base: u32,
+    print: PrintOptions,
+    input: String,
+}
+
+fn parse_disasm_args(mut args: impl Iterator<Item = String>) -> Result<DisasmOptions, String> {
+    let mut base = 0;
+    let mut print = PrintOptions::default();
+    let mut input = None;
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--base" => base = parse_address(args.next())?,
+            "--no-aliases" => print.aliases = false,
+            "--numeric" => print.abi_names = false,
+            _ if arg.starts_with('-') => return Err(format!("Unknown option `{}`", arg)),
+            _ if input.is_some() => return Err("`disasm` takes a single input file".to_string()),
+            _ => input = Some(arg)
+        }
+    }
+
+    Ok(DisasmOptions { base, print, input: input.ok_or("No input file")? })
+}
+
+/// Options of the `run`, `debug` and `gdb` subcommands
+struct RunOptions {
+    link: LinkOptions,
+    /// Stop after this many instructions, `run` only
+    max_steps: Option<u64>,
+    /// TCP port to serve the GDB remote protocol on, `gdb` only
+    port: u16,
+    /// File to write a Spike-style commit log to, `run` only
+    commit_log: Option<String>,
+    inputs: Vec<String>,
+}
+
+fn parse_run_args(command: &str, mut args: impl Iterator<Item = String>) -> Result<RunOptions, String> {
+    let mut options = RunOptions { link: LinkOptions::default(), max_steps: None, port: DEFAULT_GDB_PORT, commit_log: None, inputs: Vec::new() };
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--base" => options.link.base = parse_address(args.next())?,
+            "--entry" => {
+                options.link.entry = Some(args.next().ok_or("Missing entry symbol")?);
+            },
+            "--max-steps" if command == "run" => {
+                let value = args.next().ok_or("Missing step count")?;
+                options.max_steps = Some(value.parse().map_err(|_| format!("Invalid step count `{}`", value))?);
             },
+            "--log-commits" if command == "run" => {
+                options.commit_log = Some(args.next().ok_or("Missing commit log file name")?);
+            },
+            "--port" if command == "gdb" => {
+                let value = args.next().ok_or("Missing port")?;
+                options.port = value.parse().map_err(|_| format!("Invalid port `{}`", value))?;
+            },
+            _ if arg.starts_with('-') => return Err(format!("Unknown option `{}`", arg)),
+            _ => options.inputs.push(arg)
+        }
+    }
+
+    if options.inputs.is_empty() {
+        return Err("No input file".to_string());
+    }
+    Ok(options)
+}
+
+fn parse_address(value: Option<String>) -> Result<u32, String> {
+    let value = value.ok_or("Missing base address")?;
+    expr::eval_absolute(&value, &SymbolTable::default())
+        .ok()
+        .and_then(|base| u32::try_from(base).ok())
+        .ok_or(format!("Invalid base address `{}`", value))
+}
+
+fn main() {
+    let mut args = env::args().skip(1).peekable();
+    if args.next_if(|arg| arg == "--explain").is_some() {
+        let code = args.next().unwrap_or_default();
+        match explain::explain(&code) {
+            Some(text) => print!("{}", text),
+            None => {
+                eprintln!("Error: Unknown error code `{}`\n{}", code, USAGE);
+                std::process::exit(1);
+            }
+        }
+        return;
+    }
+
+    if args.next_if(|arg| arg == "disasm").is_some() {
+        match parse_disasm_args(args) {
+            Ok(options) => disasm(&options),
+            Err(e) => {
+                eprintln!("Error: {}\n{}", e, USAGE);
+                std::process::exit(1);
+            }
+        }
+        return;
+    }
 
+    if let Some(command) = args.next_if(|arg| ["run", "debug", "gdb"].contains(&arg.as_str())) {
+        match parse_run_args(&command, args) {
+            Ok(options) if command == "run" => run(&options),
+            Ok(options) if command == "debug" => debug(&options),
+            Ok(options) => gdb(&options),
             Err(e) => {
-                eprintln!("{:?}", e);
+                eprintln!("Error: {}\n{}", e, USAGE);
+                std::process::exit(1);
             }
         }
-    } 
+        return;
+    }
+
+    let options = match parse_args(args) {
+        Ok(options) => options,
+        Err(e) => {
+            eprintln!("Error: {}\n{}", e, USAGE);
+            std::process::exit(1);
+        }
+    };
+
+    let (sources, objects) = assemble_all(&options.inputs, !options.compile_only);
+
+    let units = build_units(&options.inputs, &sources, &objects);
+
+    if options.compile_only {
+        for (arg, object) in options.inputs.iter().zip(&objects) {
+            let output = output_name(&options.output, arg, Some("o"));
+            write(&output, elf::write_relocatable(object));
+        }
+        if let Some(listing_file) = &options.listing {
+            write(listing_file, Ok(listing::write(&units, None)));
+        }
+        return;
+    }
+
+    let image = link_or_exit(&objects, &options.link);
+    let output = output_name(&options.output, &options.inputs[0], options.format.extension());
+    write(&output, options.format.write(&image, &options.write));
+    if let Some(listing_file) = &options.listing {
+        write(listing_file, Ok(listing::write(&units, Some(&image))));
+    }
+}
+
+/// Read and assemble every input, reporting every error and warning with a summary count.
+/// Exits if there were errors. With `link`, references no input defines are errors too.
+fn assemble_all(inputs: &[String], link: bool) -> (Vec<String>, Vec<Object>) {
+    let mut sources: Vec<String> = Vec::new();
+    let mut objects: Vec<Object> = Vec::new();
+    let mut diagnostics: Vec<Vec<Diagnostic>> = Vec::new();
+    let mut names = Vec::new();
+    let mut unreadable = 0;
+    for arg in inputs {
+        match file::read_asm(arg) {
+            Ok(content) => {
+                let (object, found) = assembler::assemble(&content);
+                names.push(arg);
+                sources.push(content);
+                objects.push(object);
+                diagnostics.push(found);
+            },
+
+            Err(e) => {
+                eprintln!("error: {}: {}", arg, e);
+                unreadable += 1;
+            }
+        }
+    }
+
+    if link && unreadable == 0 {
+        for (unit, name, location) in linker::undefined_symbols(&objects) {
+            let line = objects[unit].line_at(location).unwrap_or(0);
+            diagnostics[unit].push(Diagnostic::find(AsmRiscVError::UndefinedSymbol(name.to_string()), &sources[unit], line, name));
+        }
+    }
+
+    let (mut errors, mut warnings) = (unreadable, 0);
+    let (mut error_codes, mut warning_codes) = (Vec::new(), Vec::new());
+    for ((arg, source), found) in names.iter().zip(&sources).zip(&mut diagnostics) {
+        found.sort_by_key(|diagnostic| diagnostic.line);
+        for diagnostic in found.iter() {
+            eprint!("{}", diagnostic.render(arg, source));
+            let codes = if diagnostic.is_error() {
+                errors += 1;
+                &mut error_codes
+            } else {
+                warnings += 1;
+                &mut warning_codes
+            };
+            if !codes.contains(&diagnostic.error.code()) {
+                codes.push(diagnostic.error.code());
+            }
+        }
+    }
+    // Point at what stopped the build, and only at warnings when nothing did
+    let codes = if error_codes.is_empty() { warning_codes } else { error_codes };
+
+    if let Some(summary) = diagnostic::summary(errors, warnings) {
+        eprintln!("{}", summary);
+    }
+    let (this, any) = if errors > 0 { ("this error", "an error") } else { ("this warning", "a warning") };
+    match codes.as_slice() {
+        [] => {},
+        [code] => eprintln!("For more information about {}, try `cargo run --explain {}`.", this, code),
+        [code, ..] => eprintln!("For more information about {}, try `cargo run --explain {}`.", any, code),
+    }
+    if errors > 0 {
+        std::process::exit(1);
+    }
+    (sources, objects)
+}
+
+/// Link the assembled inputs, exiting with the error if they can not be linked
+fn link_or_exit(objects: &[Object], options: &LinkOptions) -> Image {
+    match linker::link(objects, options) {
+        Ok(image) => image,
+        Err(e) => {
+            eprintln!("error[{}]: {}", e.code(), e);
+            std::process::exit(1);
+        }
+    }
+}
+
+/// Pair every input with its source and object for listings and the debugger
+fn build_units<'a>(inputs: &'a [String], sources: &'a [String], objects: &'a [Object]) -> Vec<listing::Unit<'a>> {
+    inputs.iter()
+          .zip(sources)
+          .zip(objects)
+          .map(|((name, source), object)| listing::Unit { name, source, object })
+          .collect()
+}
+
+/// Assemble and link a program, then debug it with commands read from stdin
+fn debug(options: &RunOptions) {
+    let (sources, objects) = assemble_all(&options.inputs, true);
+    let image = link_or_exit(&objects, &options.link);
+    let units = build_units(&options.inputs, &sources, &objects);
+
+    let stdin = io::stdin();
+    let runtime = Runtime::new(&image, stdin.lock(), io::stdout().lock());
+    if let Err(e) = Debugger::new(&image, &units, runtime).run() {
+        eprintln!("{:?}", e);
+        std::process::exit(1);
+    }
+}
+
+/// Assemble and link a program, then serve it to one GDB remote protocol client on localhost
+fn gdb(options: &RunOptions) {
+    let (_, objects) = assemble_all(&options.inputs, true);
+    let image = link_or_exit(&objects, &options.link);
+
+    let stdin = io::stdin();
+    let runtime = Runtime::new(&image, stdin.lock(), io::stdout());
+    let mut target = Target::new(Machine::new(&image), runtime);
+    let result = TcpListener::bind(("127.0.0.1", options.port)).and_then(|listener| {
+        eprintln!("Listening for GDB on {}", listener.local_addr()?);
+        let (stream, peer) = listener.accept()?;
+        eprintln!("Connected to {}", peer);
+        target.serve(&mut Connection::new(stream))
+    });
+    if let Err(e) = result {
+        eprintln!("{:?}", e);
+        std::process::exit(1);
+    }
+}
+
+/// Assemble, link and execute a program with RARS system calls until it stops
+fn run(options: &RunOptions) {
+    let (_, objects) = assemble_all(&options.inputs, true);
+    let image = link_or_exit(&objects, &options.link);
+
+    let mut commit_log = options.commit_log.as_ref().map(|path| match File::create(path) {
+        Ok(log) => CommitLog::new(BufWriter::new(log)),
+        Err(e) => {
+            eprintln!("{}: {:?}", path, e);
+            std::process::exit(1);
+        }
+    });
+
+    let mut machine = Machine::new(&image);
+    let stdin = io::stdin();
+    let mut runtime = Runtime::new(&image, stdin.lock(), BufWriter::new(io::stdout()));
+    let stop = runtime.run(&mut machine, options.max_steps, |commit| {
+        if let Some(log) = &mut commit_log {
+            log.record(commit);
+        }
+    });
+    if let (Some(log), Some(path)) = (commit_log, &options.commit_log) && let Err(e) = log.finish() {
+        eprintln!("{}: {}", path, e);
+        std::process::exit(1);
+    }
+
+    // The program owns stdout, so anything about how it ended goes to stderr
+    match stop {
+        Stop::Exit(code) => std::process::exit(code),
+        _ => {
+            eprintln!("Stopped at {:#010x} after {} instructions: {}", machine.pc, machine.instret, stop);
+            eprint!("{}", machine.dump_registers());
+            if stop != Stop::Ebreak {
+                std::process::exit(1);
+            }
+        }
+    }
+}
+
+/// Print the `.text` of an ELF file, or a whole raw binary placed at `options.base`
+fn disasm(options: &DisasmOptions) {
+    let bytes = match file::read_binary(&options.input) {
+        Ok(bytes) => bytes,
+        Err(e) => {
+            eprintln!("{}: {:?}", options.input, e);
+            std::process::exit(1);
+        }
+    };
+
+    let text = if bytes.starts_with(b"\x7fELF") {
+        match elf::read_text(&bytes) {
+            Ok(text) => text,
+            Err(e) => {
+                eprintln!("error[{}]: {}\n --> {}", e.code(), e, options.input);
+                std::process::exit(1);
+            }
+        }
+    } else {
+        elf::TextSection { address: options.base, bytes, symbols: Vec::new() }
+    };
+
+    let lines = disassembler::disassemble(&text.bytes, text.address);
+    print!("{}", disassembler::render(&lines, &text.symbols, &options.print));
+}
+
+/// The `-o` file when one is given, otherwise the default one for `input`
+fn output_name(output: &Option<String>, input: &str, extension: Option<&str>) -> String {
+    if let Some(output) = output {
+        return output.clone();
+    }
+    match file::default_output(input, extension) {
+        Ok(output) => output,
+        Err(e) => {
+            eprintln!("error: {}: {}, use -o to name it", input, e);
+            std::process::exit(1);
+        }
+    }
+}
+
+fn write(output: &str, contents: Result<Vec<u8>, AsmRiscVError>) {
+    let contents = match contents {
+        Ok(contents) => contents,
+        Err(e) => {
+            eprintln!("error[{}]: {}", e.code(), e);
+            std::process::exit(1);
+        }
+    };
+
+    if let Err(e) = file::write_output(output, &contents) {
+        eprintln!("{:?}", e);
+        std::process::exit(1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::parse_args;
+
+    fn parse(args: &[&str]) -> Result<(), String> {
+        parse_args(args.iter().map(|arg| arg.to_string())).map(|_| ())
+    }
+
+    #[test]
+    fn rejects_unsupported_word_sizes() {
+        assert!(parse(&["--word-size", "64", "rom.s"]).is_ok());
+        for size in ["12", "0", "128", "wide"] {
+            assert_eq!(parse(&["--word-size", size, "rom.s"]), Err(format!("Invalid word size `{}`, expected 8, 16, 32 or 64", size)));
+        }
+    }
+
+    #[test]
+    fn rejects_address_widths_the_format_can_not_index() {
+        assert!(parse(&["-f", "sv", "--address-width", "32", "rom.s"]).is_ok());
+        assert!(parse(&["--address-width", "31", "-f", "vhdl", "rom.s"]).is_ok());
+        assert_eq!(parse(&["--address-width", "32", "-f", "vhdl", "rom.s"]),
+                   Err("Address width 32 is wider than the 31 bits this format supports".to_string()));
+        assert_eq!(parse(&["-f", "sv", "--address-width", "33", "rom.s"]),
+                   Err("Address width 33 is wider than the 32 bits this format supports".to_string()));
+        assert_eq!(parse(&["--address-width", "0", "rom.s"]), Err("Invalid address width `0`".to_string()));
+    }
 }
@@ -0,0 +1,105 @@
+pub mod elf;
+pub mod ihex;
+pub mod listing;
+pub mod mem;
+pub mod rom;
+pub mod srec;
+
+use crate::linker::Image;
+use crate::utils::exception::AsmRiscVError;
+
+use self::mem::Radix;
+use self::rom::RomStyle;
+
+/// Settings for formats that can be tuned from the command line
+#[derive(Debug, Clone, Default)]
+pub struct WriteOptions {
+    /// Put `@address` markers in `$readmemh`/`$readmemb` files
+    pub address_markers: bool,
+    /// Name of the generated ROM module or entity, `rom` by default
+    pub rom_name: Option<String>,
+    pub rom_style: RomStyle,
+    /// ROM address bus width, by default the narrowest one that holds the program
+    pub address_width: Option<u32>,
+    /// Bits per ROM word: 8, 16, 32 (the default) or 64
+    pub word_size: Option<u32>,
+}
+
+/// File formats a linked program can be written as
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    /// Raw memory contents from the start of `.text`, as produced by `Image::flat_binary`
+    Binary,
+    /// ELF32 executable
+    Elf,
+    /// Intel HEX with 32-bit extended linear addresses
+    IntelHex,
+    /// Motorola S-records
+    Srec,
+    /// Verilog `$readmemh` text
+    ReadMemH,
+    /// Verilog `$readmemb` text
+    ReadMemB,
+    /// Xilinx `.coe` coefficient file
+    Coe,
+    /// Intel/Altera `.mif` memory initialisation file
+    Mif,
+    /// One 32-bit binary string per line
+    BinaryText,
+    /// SystemVerilog ROM module
+    SystemVerilog,
+    /// VHDL ROM entity
+    Vhdl,
+}
+
+impl Format {
+    pub fn from_name(name: &str) -> Option<Format> {
+        match name {
+            "bin" | "binary" => Some(Format::Binary),
+            "elf" => Some(Format::Elf),
+            "hex" | "ihex" => Some(Format::IntelHex),
+            "srec" | "s19" | "s28" | "s37" => Some(Format::Srec),
+            "readmemh" | "memh" => Some(Format::ReadMemH),
+            "readmemb" | "memb" => Some(Format::ReadMemB),
+            "coe" => Some(Format::Coe),
+            "mif" => Some(Format::Mif),
+            "bintext" | "txt" => Some(Format::BinaryText),
+            "sv" | "systemverilog" => Some(Format::SystemVerilog),
+            "vhd" | "vhdl" => Some(Format::Vhdl),
+            _ => None
+        }
+    }
+
+    /// Extension appended to the input file stem when no output name is given
+    pub fn extension(&self) -> Option<&'static str> {
+        match self {
+            Format::Binary => None,
+            Format::Elf => Some("elf"),
+            Format::IntelHex => Some("hex"),
+            Format::Srec => Some("srec"),
+            Format::ReadMemH => Some("memh"),
+            Format::ReadMemB => Some("memb"),
+            Format::Coe => Some("coe"),
+            Format::Mif => Some("mif"),
+            Format::BinaryText => Some("txt"),
+            Format::SystemVerilog => Some("sv"),
+            Format::Vhdl => Some("vhd"),
+        }
+    }
+
+    pub fn write(&self, image: &Image, options: &WriteOptions) -> Result<Vec<u8>, AsmRiscVError> {
+        match self {
+            Format::Binary => Ok(image.flat_binary()),
+            Format::Elf => elf::write_executable(image),
+            Format::IntelHex => Ok(ihex::write(image)),
+            Format::Srec => Ok(srec::write(image)),
+            Format::ReadMemH => Ok(mem::readmem(image, Radix::Hex, options.address_markers)),
+            Format::ReadMemB => Ok(mem::readmem(image, Radix::Binary, options.address_markers)),
+            Format::Coe => Ok(mem::coe(image)),
+            Format::Mif => Ok(mem::mif(image)),
+            Format::BinaryText => Ok(mem::readmem(image, Radix::Binary, false)),
+            Format::SystemVerilog => rom::system_verilog(image, options),
+            Format::Vhdl => rom::vhdl(image, options),
+        }
+    }
+}
@@ -0,0 +1,596 @@
+use crate::assembler::Object;
+use crate::linker::Image;
+use crate::assembler::reloc::{RelocKind, Relocation, Target};
+use crate::assembler::section::{Label, Section};
+use crate::utils::exception::AsmRiscVError;
+
+const EM_RISCV: u16 = 243;
+const ET_REL: u16 = 1;
+const ET_EXEC: u16 = 2;
+
+const PT_LOAD: u32 = 1;
+
+const PF_X: u32 = 0x1;
+const PF_W: u32 = 0x2;
+const PF_R: u32 = 0x4;
+
+const SHT_PROGBITS: u32 = 1;
+const SHT_SYMTAB: u32 = 2;
+const SHT_STRTAB: u32 = 3;
+const SHT_RELA: u32 = 4;
+const SHT_NOBITS: u32 = 8;
+
+const SHF_WRITE: u32 = 0x1;
+const SHF_ALLOC: u32 = 0x2;
+const SHF_EXECINSTR: u32 = 0x4;
+const SHF_INFO_LINK: u32 = 0x40;
+
+const SHN_UNDEF: u16 = 0;
+const SHN_ABS: u16 = 0xfff1;
+
+const STB_LOCAL: u8 = 0;
+const STB_GLOBAL: u8 = 1;
+const STT_NOTYPE: u8 = 0;
+const STT_SECTION: u8 = 3;
+
+const R_RISCV_32: u32 = 1;
+const R_RISCV_BRANCH: u32 = 16;
+const R_RISCV_JAL: u32 = 17;
+const R_RISCV_CALL_PLT: u32 = 19;
+const R_RISCV_PCREL_HI20: u32 = 23;
+const R_RISCV_PCREL_LO12_I: u32 = 24;
+const R_RISCV_PCREL_LO12_S: u32 = 25;
+const R_RISCV_HI20: u32 = 26;
+const R_RISCV_LO12_I: u32 = 27;
+const R_RISCV_LO12_S: u32 = 28;
+
+const EHDR_SIZE: u32 = 52;
+const PHDR_SIZE: u32 = 32;
+const SHDR_SIZE: u32 = 40;
+
+/// Null-separated string section with a leading empty name
+struct StringTable(Vec<u8>);
+
+impl StringTable {
+    fn new() -> StringTable {
+        StringTable(vec![0])
+    }
+
+    fn add(&mut self, name: &str) -> u32 {
+        let offset = self.0.len() as u32;
+        self.0.extend_from_slice(name.as_bytes());
+        self.0.push(0);
+        offset
+    }
+}
+
+struct SectionHeader {
+    name: u32,
+    kind: u32,
+    flags: u32,
+    addr: u32,
+    offset: u32,
+    size: u32,
+    link: u32,
+    info: u32,
+    align: u32,
+    entsize: u32,
+}
+
+struct ProgramHeader {
+    kind: u32,
+    offset: u32,
+    vaddr: u32,
+    filesz: u32,
+    memsz: u32,
+    flags: u32,
+    align: u32,
+}
+
+struct Symbol {
+    name: u32,
+    value: u32,
+    info: u8,
+    shndx: u16,
+}
+
+/// Lays out section contents after the ELF and program headers and appends the section header table
+struct ElfBuilder {
+    contents: Vec<u8>,
+    headers: Vec<SectionHeader>,
+    shstrtab: StringTable,
+    segments: usize,
+}
+
+impl ElfBuilder {
+    /// Room is left for `segments` program headers right after the ELF header
+    fn new(segments: usize) -> ElfBuilder {
+        ElfBuilder {
+            contents: vec![0; (EHDR_SIZE + PHDR_SIZE * segments as u32) as usize],
+            headers: vec![SectionHeader { name: 0, kind: 0, flags: 0, addr: 0, offset: 0, size: 0, link: 0, info: 0, align: 0, entsize: 0 }],
+            shstrtab: StringTable::new(),
+            segments,
+        }
+    }
+
+    /// Append a section and return its index. `NOBITS` sections take `size` but no contents.
+    #[allow(clippy::too_many_arguments)]
+    fn add_section(&mut self, name: &str, kind: u32, flags: u32, bytes: &[u8], size: u32, link: u32, info: u32, align: u32, entsize: u32) -> u32 {
+        while !self.contents.len().is_multiple_of(align.max(1) as usize) {
+            self.contents.push(0);
+        }
+
+        let header = SectionHeader {
+            name: self.shstrtab.add(name),
+            kind,
+            flags,
+            addr: 0,
+            offset: self.contents.len() as u32,
+            size,
+            link,
+            info,
+            align,
+            entsize,
+        };
+        self.contents.extend_from_slice(bytes);
+        self.headers.push(header);
+        (self.headers.len() - 1) as u32
+    }
+
+    /// Give an already added section its load address
+    fn set_addr(&mut self, index: u32, addr: u32) {
+        self.headers[index as usize].addr = addr;
+    }
+
+    fn header(&self, index: u32) -> &SectionHeader {
+        &self.headers[index as usize]
+    }
+
+    fn finish(mut self, e_type: u16, entry: u32, segments: &[ProgramHeader]) -> Vec<u8> {
+        assert_eq!(segments.len(), self.segments);
+
+        let shstrtab = std::mem::replace(&mut self.shstrtab, StringTable::new());
+        let shstrndx = self.headers.len() as u32;
+        let name = shstrtab.0.len() as u32;
+        let mut names = shstrtab.0;
+        names.extend_from_slice(b".shstrtab\0");
+        self.add_section("", SHT_STRTAB, 0, &names, names.len() as u32, 0, 0, 1, 0);
+        if let Some(header) = self.headers.last_mut() {
+            header.name = name;
+        }
+
+        while !self.contents.len().is_multiple_of(4) {
+            self.contents.push(0);
+        }
+        let shoff = self.contents.len() as u32;
+        for header in &self.headers {
+            for field in [header.name, header.kind, header.flags, header.addr, header.offset,
+                          header.size, header.link, header.info, header.align, header.entsize] {
+                self.contents.extend_from_slice(&field.to_le_bytes());
+            }
+        }
+
+        let phoff = if segments.is_empty() { 0 } else { EHDR_SIZE };
+        let mut phdrs = Vec::with_capacity(segments.len() * PHDR_SIZE as usize);
+        for segment in segments {
+            for field in [segment.kind, segment.offset, segment.vaddr, segment.vaddr,
+                          segment.filesz, segment.memsz, segment.flags, segment.align] {
+                phdrs.extend_from_slice(&field.to_le_bytes());
+            }
+        }
+        self.contents[EHDR_SIZE as usize..EHDR_SIZE as usize + phdrs.len()].copy_from_slice(&phdrs);
+
+        let mut ehdr = Vec::with_capacity(EHDR_SIZE as usize);
+        ehdr.extend_from_slice(&[0x7f, b'E', b'L', b'F', 1, 1, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0]);
+        ehdr.extend_from_slice(&e_type.to_le_bytes());
+        ehdr.extend_from_slice(&EM_RISCV.to_le_bytes());
+        ehdr.extend_from_slice(&1_u32.to_le_bytes());
+        ehdr.extend_from_slice(&entry.to_le_bytes());
+        ehdr.extend_from_slice(&phoff.to_le_bytes());
+        ehdr.extend_from_slice(&shoff.to_le_bytes());
+        ehdr.extend_from_slice(&0_u32.to_le_bytes());
+        ehdr.extend_from_slice(&(EHDR_SIZE as u16).to_le_bytes());
+        ehdr.extend_from_slice(&(PHDR_SIZE as u16).to_le_bytes());
+        ehdr.extend_from_slice(&(segments.len() as u16).to_le_bytes());
+        ehdr.extend_from_slice(&(SHDR_SIZE as u16).to_le_bytes());
+        ehdr.extend_from_slice(&(self.headers.len() as u16).to_le_bytes());
+        ehdr.extend_from_slice(&(shstrndx as u16).to_le_bytes());
+        self.contents[..EHDR_SIZE as usize].copy_from_slice(&ehdr);
+
+        self.contents
+    }
+}
+
+/// Serialize an assembled file as an ELF32 little-endian relocatable object.
+/// Labels become local symbols unless named by `.globl`; symbols that are referenced
+/// but not defined become undefined globals for the linker to resolve.
+pub fn write_relocatable(object: &Object) -> Result<Vec<u8>, AsmRiscVError> {
+    let mut elf = ElfBuilder::new(0);
+    let mut strtab = StringTable::new();
+
+    // Section indices in the header table follow `Section::ALL`, starting at 1
+    let shndx = |section: Section| (section.index() + 1) as u16;
+    for section in Section::ALL {
+        let (kind, flags) = section_kind(section);
+        let bytes = object.section(section);
+        let contents: &[u8] = if kind == SHT_NOBITS { &[] } else { bytes };
+        elf.add_section(section.name(), kind, flags, contents, bytes.len() as u32, 0, 0, object.alignment(section), 0);
+    }
+
+    let mut symbols = vec![Symbol { name: 0, value: 0, info: 0, shndx: SHN_UNDEF }];
+    for section in Section::ALL {
+        symbols.push(Symbol { name: 0, value: 0, info: (STB_LOCAL << 4) | STT_SECTION, shndx: shndx(section) });
+    }
+
+    // `%pcrel_lo` must point at a symbol placed on its `%pcrel_hi` instruction
+    let anchors: Vec<Label> = object.relocations.iter()
+                                                .filter_map(|reloc| match (reloc.kind, &reloc.target) {
+                                                    (RelocKind::PcrelLo12, Target::Section(section)) => {
+                                                        Some(Label { section: *section, offset: reloc.addend as u32 })
+                                                    },
+                                                    _ => None
+                                                })
+                                                .collect();
+
+    // Like GNU as, `.L` labels are left out unless they are global or an anchor
+    let mut labels: Vec<(&String, &Label)> = object.symbols.labels.iter()
+                                                   .filter(|(name, label)| {
+                                                       !name.starts_with(".L") || object.globals.contains(*name) || anchors.contains(label)
+                                                   })
+                                                   .collect();
+    labels.sort_by_key(|(name, label)| (label.section.index(), label.offset, name.as_str()));
+
+    let mut constants: Vec<(&String, &i64)> = object.symbols.constants.iter().collect();
+    constants.sort();
+
+    let mut pcrel_anchors: Vec<Label> = Vec::new();
+    for anchor in anchors {
+        if !labels.iter().any(|(_, label)| **label == anchor) && !pcrel_anchors.contains(&anchor) {
+            pcrel_anchors.push(anchor);
+        }
+    }
+
+    let mut names: Vec<String> = Vec::new();
+    for (name, label) in labels.iter().filter(|(name, _)| !object.globals.contains(*name)) {
+        symbols.push(Symbol { name: strtab.add(name), value: label.offset, info: (STB_LOCAL << 4) | STT_NOTYPE, shndx: shndx(label.section) });
+        names.push(name.to_string());
+    }
+    for (i, anchor) in pcrel_anchors.iter().enumerate() {
+        let name = format!(".Lpcrel_hi{}", i);
+        symbols.push(Symbol { name: strtab.add(&name), value: anchor.offset, info: (STB_LOCAL << 4) | STT_NOTYPE, shndx: shndx(anchor.section) });
+        names.push(name);
+    }
+    for (name, value) in &constants {
+        symbols.push(Symbol { name: strtab.add(name), value: **value as u32, info: (STB_LOCAL << 4) | STT_NOTYPE, shndx: SHN_ABS });
+        names.push(name.to_string());
+    }
+
+    let first_global = symbols.len() as u32;
+    for (name, label) in labels.iter().filter(|(name, _)| object.globals.contains(*name)) {
+        symbols.push(Symbol { name: strtab.add(name), value: label.offset, info: (STB_GLOBAL << 4) | STT_NOTYPE, shndx: shndx(label.section) });
+        names.push(name.to_string());
+    }
+
+    let mut undefined: Vec<&String> = object.relocations.iter()
+                                                        .filter_map(|reloc| match &reloc.target {
+                                                            Target::Symbol(name) => Some(name),
+                                                            _ => None
+                                                        })
+                                                        .chain(object.globals.iter())
+                                                        .filter(|name| !object.symbols.contains(name))
+                                                        .collect();
+    undefined.sort();
+    undefined.dedup();
+    for name in undefined {
+        symbols.push(Symbol { name: strtab.add(name), value: 0, info: (STB_GLOBAL << 4) | STT_NOTYPE, shndx: SHN_UNDEF });
+        names.push(name.to_string());
+    }
+
+    // `names[i]` describes `symbols[i + first named]`
+    let first_named = 1 + Section::ALL.len();
+    let symbol_index = |name: &str| -> Option<u32> {
+        names.iter().position(|n| n == name).map(|i| (i + first_named) as u32)
+    };
+
+    let symtab_bytes = symbol_bytes(&symbols);
+
+    // `.strtab` directly follows `.symtab`
+    let symtab_index = elf.add_section(".symtab", SHT_SYMTAB, 0, &symtab_bytes, symtab_bytes.len() as u32,
+                                       (Section::ALL.len() + 2) as u32, first_global, 4, 16);
+    elf.add_section(".strtab", SHT_STRTAB, 0, &strtab.0, strtab.0.len() as u32, 0, 0, 1, 0);
+
+    for section in Section::ALL {
+        let relocations: Vec<&Relocation> = object.relocations.iter()
+                                                              .filter(|reloc| reloc.location.section == section)
+                                                              .collect();
+        if relocations.is_empty() {
+            continue;
+        }
+
+        let mut rela = Vec::with_capacity(relocations.len() * 12);
+        for reloc in relocations {
+            let ins = object.section(section)
+                            .get(reloc.location.offset as usize..reloc.location.offset as usize + 4)
+                            .map(|bytes| u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+                            .unwrap_or(0);
+            let is_store = ins & 0x7f == 0b0100011;
+
+            let r_type = match reloc.kind {
+                RelocKind::Hi20 => R_RISCV_HI20,
+                RelocKind::Lo12 if is_store => R_RISCV_LO12_S,
+                RelocKind::Lo12 => R_RISCV_LO12_I,
+                RelocKind::PcrelHi20 => R_RISCV_PCREL_HI20,
+                RelocKind::PcrelLo12 if is_store => R_RISCV_PCREL_LO12_S,
+                RelocKind::PcrelLo12 => R_RISCV_PCREL_LO12_I,
+                RelocKind::Branch => R_RISCV_BRANCH,
+                RelocKind::Jal => R_RISCV_JAL,
+                RelocKind::Call => R_RISCV_CALL_PLT,
+                RelocKind::Abs32 => R_RISCV_32,
+            };
+
+            let (r_sym, addend) = match (&reloc.target, reloc.kind) {
+                (Target::Section(section), RelocKind::PcrelLo12) => {
+                    let anchor = Label { section: *section, offset: reloc.addend as u32 };
+                    let name = match labels.iter().find(|(_, label)| **label == anchor) {
+                        Some((name, _)) => name.to_string(),
+                        None => format!(".Lpcrel_hi{}", pcrel_anchors.iter().position(|a| *a == anchor).unwrap_or(0)),
+                    };
+                    (symbol_index(&name).ok_or(AsmRiscVError::Internal("`%pcrel_hi` anchor missing from the symbol table"))?, 0)
+                },
+                (Target::Section(section), _) => (shndx(*section) as u32, reloc.addend),
+                (Target::Symbol(name), _) => (symbol_index(name).ok_or_else(|| AsmRiscVError::UndefinedSymbol(name.clone()))?, reloc.addend),
+                (Target::Absolute, _) => (0, reloc.addend),
+            };
+
+            rela.extend_from_slice(&reloc.location.offset.to_le_bytes());
+            rela.extend_from_slice(&((r_sym << 8) | r_type).to_le_bytes());
+            rela.extend_from_slice(&(addend as i32).to_le_bytes());
+        }
+
+        let name = format!(".rela{}", section.name());
+        elf.add_section(&name, SHT_RELA, SHF_INFO_LINK, &rela, rela.len() as u32, symtab_index, shndx(section) as u32, 4, 12);
+    }
+
+    Ok(elf.finish(ET_REL, 0, &[]))
+}
+
+/// Serialize a linked program as an ELF32 little-endian executable.
+/// Every non-empty section is loaded by its own segment; labels are kept as symbols.
+pub fn write_executable(image: &Image) -> Result<Vec<u8>, AsmRiscVError> {
+    let loaded = image.sections.iter().filter(|output| !output.bytes.is_empty()).count();
+    let mut elf = ElfBuilder::new(loaded);
+    let mut strtab = StringTable::new();
+
+    let mut segments = Vec::with_capacity(loaded);
+    for output in &image.sections {
+        let (kind, flags) = section_kind(output.section);
+        let contents: &[u8] = if kind == SHT_NOBITS { &[] } else { &output.bytes };
+        let index = elf.add_section(output.section.name(), kind, flags, contents, output.bytes.len() as u32, 0, 0, output.alignment, 0);
+        elf.set_addr(index, output.address);
+
+        if !output.bytes.is_empty() {
+            let mut segment_flags = PF_R;
+            if flags & SHF_WRITE != 0 {
+                segment_flags |= PF_W;
+            }
+            if flags & SHF_EXECINSTR != 0 {
+                segment_flags |= PF_X;
+            }
+            segments.push(ProgramHeader {
+                kind: PT_LOAD,
+                offset: elf.header(index).offset,
+                vaddr: output.address,
+                filesz: contents.len() as u32,
+                memsz: output.bytes.len() as u32,
+                flags: segment_flags,
+                align: 4,
+            });
+        }
+    }
+
+    let shndx = |section: Section| (section.index() + 1) as u16;
+    let mut symbols = vec![Symbol { name: 0, value: 0, info: 0, shndx: SHN_UNDEF }];
+    for symbol in image.symbols.iter().filter(|symbol| !symbol.global) {
+        symbols.push(Symbol { name: strtab.add(&symbol.name), value: symbol.address, info: (STB_LOCAL << 4) | STT_NOTYPE, shndx: shndx(symbol.section) });
+    }
+    let first_global = symbols.len() as u32;
+    for symbol in image.symbols.iter().filter(|symbol| symbol.global) {
+        symbols.push(Symbol { name: strtab.add(&symbol.name), value: symbol.address, info: (STB_GLOBAL << 4) | STT_NOTYPE, shndx: shndx(symbol.section) });
+    }
+
+    let symtab_bytes = symbol_bytes(&symbols);
+    elf.add_section(".symtab", SHT_SYMTAB, 0, &symtab_bytes, symtab_bytes.len() as u32,
+                    (Section::ALL.len() + 2) as u32, first_global, 4, 16);
+    elf.add_section(".strtab", SHT_STRTAB, 0, &strtab.0, strtab.0.len() as u32, 0, 0, 1, 0);
+
+    Ok(elf.finish(ET_EXEC, image.start(), &segments))
+}
+
+/// Section type and flags of an output section
+fn section_kind(section: Section) -> (u32, u32) {
+    match section {
+        Section::Text => (SHT_PROGBITS, SHF_ALLOC | SHF_EXECINSTR),
+        Section::Rodata => (SHT_PROGBITS, SHF_ALLOC),
+        Section::Data => (SHT_PROGBITS, SHF_ALLOC | SHF_WRITE),
+        Section::Bss => (SHT_NOBITS, SHF_ALLOC | SHF_WRITE),
+    }
+}
+
+fn symbol_bytes(symbols: &[Symbol]) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(symbols.len() * 16);
+    for symbol in symbols {
+        bytes.extend_from_slice(&symbol.name.to_le_bytes());
+        bytes.extend_from_slice(&symbol.value.to_le_bytes());
+        bytes.extend_from_slice(&0_u32.to_le_bytes());
+        bytes.push(symbol.info);
+        bytes.push(0);
+        bytes.extend_from_slice(&symbol.shndx.to_le_bytes());
+    }
+    bytes
+}
+
+/// Code and symbols of the `.text` section of an ELF file
+#[derive(Debug, Clone, Default)]
+pub struct TextSection {
+    pub address: u32,
+    pub bytes: Vec<u8>,
+    /// Symbols defined in `.text`, sorted by address
+    pub symbols: Vec<(u32, String)>,
+}
+
+/// Read the `.text` section and its symbols back from a little-endian ELF32 file
+pub fn read_text(elf: &[u8]) -> Result<TextSection, AsmRiscVError> {
+    if elf.get(..6) != Some(&[0x7f, b'E', b'L', b'F', 1, 1]) {
+        return Err(AsmRiscVError::InvalidElf);
+    }
+
+    let u16_at = |offset: usize| elf.get(offset..offset + 2).map(|b| u16::from_le_bytes([b[0], b[1]])).ok_or(AsmRiscVError::InvalidElf);
+    let u32_at = |offset: usize| elf.get(offset..offset + 4).map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]])).ok_or(AsmRiscVError::InvalidElf);
+
+    let shoff = u32_at(0x20)? as usize;
+    let shnum = u16_at(0x30)? as usize;
+    let shstrndx = u16_at(0x32)? as usize;
+    let header = |index: usize| -> Result<SectionHeader, AsmRiscVError> {
+        let base = shoff + index * SHDR_SIZE as usize;
+        Ok(SectionHeader {
+            name: u32_at(base)?,
+            kind: u32_at(base + 4)?,
+            flags: u32_at(base + 8)?,
+            addr: u32_at(base + 12)?,
+            offset: u32_at(base + 16)?,
+            size: u32_at(base + 20)?,
+            link: u32_at(base + 24)?,
+            info: u32_at(base + 28)?,
+            align: u32_at(base + 32)?,
+            entsize: u32_at(base + 36)?,
+        })
+    };
+    let headers = (0..shnum).map(header).collect::<Result<Vec<_>, _>>()?;
+    let contents = |header: &SectionHeader| -> Result<&[u8], AsmRiscVError> {
+        let start = header.offset as usize;
+        elf.get(start..start + header.size as usize).ok_or(AsmRiscVError::InvalidElf)
+    };
+    let name = |strtab: &[u8], offset: u32| -> String {
+        let bytes = strtab.get(offset as usize..).unwrap_or(&[]);
+        let end = bytes.iter().position(|byte| *byte == 0).unwrap_or(bytes.len());
+        String::from_utf8_lossy(&bytes[..end]).into_owned()
+    };
+
+    let shstrtab = contents(headers.get(shstrndx).ok_or(AsmRiscVError::InvalidElf)?)?;
+    let text_index = headers.iter()
+                            .position(|header| name(shstrtab, header.name) == ".text")
+                            .ok_or(AsmRiscVError::InvalidElf)?;
+    let text = &headers[text_index];
+
+    let mut symbols = Vec::new();
+    for symtab in headers.iter().filter(|header| header.kind == SHT_SYMTAB) {
+        let strtab = contents(headers.get(symtab.link as usize).ok_or(AsmRiscVError::InvalidElf)?)?;
+        for symbol in contents(symtab)?.chunks_exact(16) {
+            let symbol_name = name(strtab, u32::from_le_bytes([symbol[0], symbol[1], symbol[2], symbol[3]]));
+            let value = u32::from_le_bytes([symbol[4], symbol[5], symbol[6], symbol[7]]);
+            let shndx = u16::from_le_bytes([symbol[14], symbol[15]]);
+            if shndx as usize == text_index && symbol[12] & 0xf != STT_SECTION && !symbol_name.is_empty() {
+                symbols.push((value, symbol_name));
+            }
+        }
+    }
+    symbols.sort();
+
+    Ok(TextSection { address: text.addr, bytes: contents(text)?.to_vec(), symbols })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::assembler;
+
+    /// A relocation read back as section, offset, type and symbol name
+    type Rela = (String, u32, u32, String);
+
+    /// `.symtab` names with their binding, and every relocation
+    fn read_back(elf: &[u8]) -> (Vec<(String, u8)>, Vec<Rela>) {
+        let u32_at = |offset: usize| u32::from_le_bytes(elf[offset..offset + 4].try_into().unwrap());
+        let shoff = u32_at(0x20) as usize;
+        let shnum = u16::from_le_bytes([elf[0x30], elf[0x31]]) as usize;
+        let shstrndx = u16::from_le_bytes([elf[0x32], elf[0x33]]) as usize;
+        // Name, kind, contents and info of each section
+        let sections: Vec<(u32, u32, &[u8], u32)> = (0..shnum).map(|index| {
+                                                                  let base = shoff + index * SHDR_SIZE as usize;
+                                                                  let (offset, size) = (u32_at(base + 16) as usize, u32_at(base + 20) as usize);
+                                                                  (u32_at(base), u32_at(base + 4), &elf[offset..offset + size], u32_at(base + 28))
+                                                              })
+                                                              .collect();
+        let name = |strtab: &[u8], offset: u32| {
+            let bytes = &strtab[offset as usize..];
+            String::from_utf8(bytes[..bytes.iter().position(|byte| *byte == 0).unwrap()].to_vec()).unwrap()
+        };
+        let section_name = |index: usize| name(sections[shstrndx].2, sections[index].0);
+        let find = |wanted: &str| (0..shnum).find(|index| section_name(*index) == wanted).unwrap();
+
+        let strtab = sections[find(".strtab")].2;
+        let symbols: Vec<(String, u8)> = sections[find(".symtab")].2.chunks_exact(16)
+                                                                    .map(|symbol| (name(strtab, u32::from_le_bytes(symbol[..4].try_into().unwrap())), symbol[12] >> 4))
+                                                                    .collect();
+
+        let mut relocations = Vec::new();
+        for (index, section) in sections.iter().enumerate().filter(|(_, section)| section.1 == SHT_RELA) {
+            for rela in section.2.chunks_exact(12) {
+                let info = u32::from_le_bytes(rela[4..8].try_into().unwrap());
+                let symbol = match &symbols[(info >> 8) as usize] {
+                    (name, _) if name.is_empty() => Section::ALL[(info >> 8) as usize - 1].name().to_string(),
+                    (name, _) => name.clone()
+                };
+                let offset = u32::from_le_bytes(rela[..4].try_into().unwrap());
+                relocations.push((section_name(index), offset, info & 0xff, symbol));
+            }
+            // `sh_info` names the section the relocations apply to
+            assert_eq!(Some(section_name(section.3 as usize).as_str()), section_name(index).strip_prefix(".rela"));
+        }
+        (symbols, relocations)
+    }
+
+    #[test]
+    fn relocatable_keeps_symbol_spelling() {
+        let source = "\
+.globl Main
+Main:
+    CALL printf
+    tail myFunc
+.Lhi:
+    AUIPC a0, %PCREL_HI(Buffer)
+    addi a0, A0, %pcrel_lo(.Lhi)
+.Lloop:
+    beq a0, x0, .Lloop
+    lui t0, %hi(External)
+    sw t0, %lo(External)(t0)
+.data
+Buffer: .word Main, External
+";
+        let (object, diagnostics) = assembler::assemble(source);
+        assert!(diagnostics.is_empty(), "{:?}", diagnostics);
+        let (symbols, relocations) = read_back(&write_relocatable(&object).unwrap());
+
+        let named: Vec<(&str, u8)> = symbols.iter()
+                                            .filter(|(name, _)| !name.is_empty())
+                                            .map(|(name, bind)| (name.as_str(), *bind))
+                                            .collect();
+        assert_eq!(named, [
+            (".Lhi", STB_LOCAL), ("Buffer", STB_LOCAL),
+            ("Main", STB_GLOBAL), ("External", STB_GLOBAL), ("myFunc", STB_GLOBAL), ("printf", STB_GLOBAL),
+        ]);
+
+        let relocations: Vec<(&str, u32, u32, &str)> = relocations.iter()
+                                                                  .map(|(section, offset, kind, symbol)| (section.as_str(), *offset, *kind, symbol.as_str()))
+                                                                  .collect();
+        assert_eq!(relocations, [
+            (".rela.text", 0x00, R_RISCV_CALL_PLT, "printf"),
+            (".rela.text", 0x08, R_RISCV_CALL_PLT, "myFunc"),
+            (".rela.text", 0x10, R_RISCV_PCREL_HI20, ".data"),
+            (".rela.text", 0x14, R_RISCV_PCREL_LO12_I, ".Lhi"),
+            (".rela.text", 0x1c, R_RISCV_HI20, "External"),
+            (".rela.text", 0x20, R_RISCV_LO12_S, "External"),
+            (".rela.data", 0x00, R_RISCV_32, ".text"),
+            (".rela.data", 0x04, R_RISCV_32, "External"),
+        ]);
+    }
+}
@@ -0,0 +1,57 @@
+use crate::linker::Image;
+
+const DATA: u8 = 0x00;
+const END_OF_FILE: u8 = 0x01;
+const EXTENDED_LINEAR_ADDRESS: u8 = 0x04;
+const START_LINEAR_ADDRESS: u8 = 0x05;
+
+/// Data bytes per record
+const RECORD_LEN: usize = 16;
+
+/// Serialize a linked program as Intel HEX.
+/// Data records carry 16-bit addresses, so an extended linear address record is
+/// emitted whenever the upper half of the address changes.
+pub fn write(image: &Image) -> Vec<u8> {
+    let mut hex = String::new();
+    let mut upper = 0_u16;
+
+    for output in image.loaded() {
+        let mut address = output.address;
+        let mut rest = output.bytes.as_slice();
+        while !rest.is_empty() {
+            if (address >> 16) as u16 != upper {
+                upper = (address >> 16) as u16;
+                record(&mut hex, EXTENDED_LINEAR_ADDRESS, 0, &upper.to_be_bytes());
+            }
+
+            // A record may not wrap around a 64 KiB boundary
+            let len = rest.len().min(RECORD_LEN).min(0x10000 - (address & 0xffff) as usize);
+            record(&mut hex, DATA, address as u16, &rest[..len]);
+            address += len as u32;
+            rest = &rest[len..];
+        }
+    }
+
+    if let Some(entry) = image.entry {
+        record(&mut hex, START_LINEAR_ADDRESS, 0, &entry.to_be_bytes());
+    }
+    record(&mut hex, END_OF_FILE, 0, &[]);
+
+    hex.into_bytes()
+}
+
+/// `:LLAAAATT<data>CC` where `CC` makes the sum of every byte zero
+fn record(hex: &mut String, kind: u8, address: u16, data: &[u8]) {
+    let mut bytes = vec![data.len() as u8];
+    bytes.extend_from_slice(&address.to_be_bytes());
+    bytes.push(kind);
+    bytes.extend_from_slice(data);
+    let checksum = bytes.iter().fold(0_u8, |sum, byte| sum.wrapping_add(*byte)).wrapping_neg();
+    bytes.push(checksum);
+
+    hex.push(':');
+    for byte in bytes {
+        hex.push_str(&format!("{:02X}", byte));
+    }
+    hex.push('\n');
+}
@@ -0,0 +1,121 @@
+use crate::assembler::{LineInfo, Object};
+use crate::assembler::reloc::Target;
+use crate::assembler::section::Section;
+use crate::linker::Image;
+
+/// Bytes shown on one listing row
+const ROW_BYTES: usize = 4;
+/// Rows shown for a single statement, like the default of `as --listing-cont-lines`
+const MAX_ROWS: usize = 4;
+
+/// One assembled source file
+pub struct Unit<'a> {
+    pub name: &'a str,
+    pub source: &'a str,
+    pub object: &'a Object,
+}
+
+/// Render a listing in the style of `as -al`: every source line with its number, the
+/// address and encoded contents of the statements on it, then a symbol table.
+/// Addresses and contents are final when `image` is the linked program, and
+/// section-relative and unrelocated otherwise.
+pub fn write(units: &[Unit], image: Option<&Image>) -> Vec<u8> {
+    let mut text = String::new();
+
+    for (i, unit) in units.iter().enumerate() {
+        let bases = image.map(|image| image.unit_bases[i]).unwrap_or([0; 4]);
+        text.push_str(&format!("LISTING {}\n\n", unit.name));
+
+        let mut lines = unit.object.lines.iter().filter(|info| info.size > 0).peekable();
+        for (number, source) in unit.source.lines().enumerate().map(|(i, source)| (i + 1, source)) {
+            let mut rows = Vec::new();
+            while let Some(info) = lines.next_if(|info| info.line == number) {
+                rows.extend(rows_of(info, unit.object, bases, image));
+            }
+
+            let mut rows = rows.into_iter();
+            match rows.next() {
+                Some((address, hex)) => text.push_str(&format!("{:>4} {:08x} {:<8}  {}\n", number, address, hex, source)),
+                None => text.push_str(&format!("{:>4} {:8} {:8}  {}\n", number, "", "", source))
+            }
+            for (address, hex) in rows {
+                text.push_str(&format!("{:>4} {:08x} {}\n", number, address, hex));
+            }
+        }
+        text.push('\n');
+    }
+
+    text.push_str("DEFINED SYMBOLS\n");
+    for (i, unit) in units.iter().enumerate() {
+        let bases = image.map(|image| image.unit_bases[i]).unwrap_or([0; 4]);
+
+        let mut labels: Vec<(u32, &String, Section)> = unit.object.symbols.labels.iter()
+                                                                         .map(|(name, label)| (bases[label.section.index()] + label.offset, name, label.section))
+                                                                         .collect();
+        labels.sort_by(|a, b| (a.2.index(), a.0, a.1).cmp(&(b.2.index(), b.0, b.1)));
+        for (address, name, section) in labels {
+            let binding = if unit.object.globals.contains(name) { "g" } else { "l" };
+            let line = unit.object.label_lines.get(name).copied().unwrap_or(0);
+            text.push_str(&format!("{:>20}:{:<6} {:>8}:{:08x} {} {}\n",
+                                   unit.name, line, section.name(), address, binding, name));
+        }
+
+        let mut constants: Vec<(&String, &i64)> = unit.object.symbols.constants.iter().collect();
+        constants.sort();
+        for (name, value) in constants {
+            text.push_str(&format!("{:>20}:{:<6} {:>8}:{:08x} l {}\n", unit.name, "", "*ABS*", *value as u32, name));
+        }
+    }
+
+    // Only a file that was not linked can leave symbols undefined
+    let mut undefined: Vec<&String> = units.iter()
+                                           .flat_map(|unit| unit.object.relocations.iter().filter_map(|reloc| match &reloc.target {
+                                               Target::Symbol(name) if !unit.object.symbols.contains(name) => Some(name),
+                                               _ => None
+                                           }))
+                                           .collect();
+    undefined.sort();
+    undefined.dedup();
+    if image.is_none() && !undefined.is_empty() {
+        text.push_str("\nUNDEFINED SYMBOLS\n");
+        for name in undefined {
+            text.push_str(&format!("{}\n", name));
+        }
+    }
+
+    text.into_bytes()
+}
+
+/// Address and hex contents of each row a statement takes
+fn rows_of(info: &LineInfo, object: &Object, bases: [u32; 4], image: Option<&Image>) -> Vec<(u32, String)> {
+    let section = info.location.section;
+    let address = bases[section.index()] + info.location.offset;
+    if section == Section::Bss {
+        return vec![(address, String::new())];
+    }
+
+    let bytes: &[u8] = match image {
+        Some(image) => {
+            let output = &image.sections[section.index()];
+            let start = (address - output.address) as usize;
+            &output.bytes[start..start + info.size as usize]
+        },
+        None => {
+            let start = info.location.offset as usize;
+            &object.section(section)[start..start + info.size as usize]
+        }
+    };
+
+    bytes.chunks(ROW_BYTES)
+         .take(MAX_ROWS)
+         .enumerate()
+         .map(|(i, chunk)| {
+             let hex = match chunk.try_into() {
+                 // Instructions read as the word they encode
+                 Ok(word) if info.instruction => format!("{:08x}", u32::from_le_bytes(word)),
+                 _ => chunk.iter().map(|byte| format!("{:02x}", byte)).collect()
+             };
+             (address + (i * ROW_BYTES) as u32, hex)
+         })
+         .collect()
+}
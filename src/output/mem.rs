@@ -0,0 +1,142 @@
+use crate::linker::Image;
+
+/// How each word is spelled out
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Radix {
+    Hex,
+    Binary,
+}
+
+impl Radix {
+    fn word(&self, word: u32) -> String {
+        match self {
+            Radix::Hex => format!("{:08x}", word),
+            Radix::Binary => format!("{:032b}", word),
+        }
+    }
+}
+
+/// Little endian words of `width` bytes covering the whole image, starting at its lowest
+/// loaded address. Gaps between sections and a partial last word are zero filled.
+pub fn words(image: &Image, width: usize) -> Vec<u64> {
+    image.flat_binary()
+         .chunks(width)
+         .map(|chunk| {
+             let mut word = [0; 8];
+             word[..chunk.len()].copy_from_slice(chunk);
+             u64::from_le_bytes(word)
+         })
+         .collect()
+}
+
+/// Verilog `$readmemh`/`$readmemb` text with one word per line.
+/// With `addresses` every section starts with an `@index` marker, counted in words from
+/// the lowest loaded address, and gaps between sections are left out.
+pub fn readmem(image: &Image, radix: Radix, addresses: bool) -> Vec<u8> {
+    let mut text = String::new();
+
+    if addresses {
+        let base = image.loaded().map(|output| output.address).min().unwrap_or(0);
+        for output in image.loaded() {
+            text.push_str(&format!("@{:x}\n", (output.address - base) / 4));
+            for chunk in output.bytes.chunks(4) {
+                let mut word = [0; 4];
+                word[..chunk.len()].copy_from_slice(chunk);
+                text.push_str(&radix.word(u32::from_le_bytes(word)));
+                text.push('\n');
+            }
+        }
+    } else {
+        for word in words(image, 4) {
+            text.push_str(&radix.word(word as u32));
+            text.push('\n');
+        }
+    }
+
+    text.into_bytes()
+}
+
+/// Words for the memory initialisation formats, which can not describe an empty memory,
+/// so an empty image becomes a single zero word.
+fn memory_words(image: &Image) -> Vec<u64> {
+    let mut words = words(image, 4);
+    if words.is_empty() {
+        words.push(0);
+    }
+    words
+}
+
+/// Xilinx coefficient file for block memory initialisation
+pub fn coe(image: &Image) -> Vec<u8> {
+    let words: Vec<String> = memory_words(image).into_iter().map(|word| Radix::Hex.word(word as u32)).collect();
+
+    let mut text = String::from("memory_initialization_radix=16;\nmemory_initialization_vector=\n");
+    text.push_str(&words.join(",\n"));
+    text.push_str(";\n");
+    text.into_bytes()
+}
+
+/// Intel/Altera memory initialisation file, one word per address
+pub fn mif(image: &Image) -> Vec<u8> {
+    let words = memory_words(image);
+
+    let mut text = format!("DEPTH = {};\nWIDTH = 32;\nADDRESS_RADIX = HEX;\nDATA_RADIX = HEX;\nCONTENT\nBEGIN\n", words.len());
+    for (address, word) in words.into_iter().enumerate() {
+        text.push_str(&format!("{:x} : {};\n", address, Radix::Hex.word(word as u32)));
+    }
+    text.push_str("END;\n");
+    text.into_bytes()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{coe, mif, readmem, Radix};
+    use crate::assembler;
+    use crate::linker::{self, Image, LinkOptions};
+
+    fn image(source: &str) -> Image {
+        let (object, diagnostics) = assembler::assemble(source);
+        assert!(diagnostics.is_empty(), "{:?}", diagnostics);
+        linker::link(&[object], &LinkOptions::default()).unwrap()
+    }
+
+    fn text(bytes: Vec<u8>) -> String {
+        String::from_utf8(bytes).unwrap()
+    }
+
+    #[test]
+    fn readmemh_words() {
+        assert_eq!(text(readmem(&image("nop\nret"), Radix::Hex, false)), "00000013\n00008067\n");
+    }
+
+    #[test]
+    fn readmemh_address_markers() {
+        let image = image(".text\nnop\n.data\n.align 4\n.word 0x12345678\n.byte 0xab");
+        assert_eq!(text(readmem(&image, Radix::Hex, true)), "@0\n00000013\n@4\n12345678\n000000ab\n");
+    }
+
+    #[test]
+    fn readmemb_words() {
+        assert_eq!(text(readmem(&image("nop"), Radix::Binary, false)), "00000000000000000000000000010011\n");
+    }
+
+    #[test]
+    fn coe_words() {
+        assert_eq!(text(coe(&image("nop\nret"))),
+                   "memory_initialization_radix=16;\nmemory_initialization_vector=\n00000013,\n00008067;\n");
+    }
+
+    #[test]
+    fn mif_words() {
+        assert_eq!(text(mif(&image("nop\nret"))),
+                   "DEPTH = 2;\nWIDTH = 32;\nADDRESS_RADIX = HEX;\nDATA_RADIX = HEX;\nCONTENT\nBEGIN\n0 : 00000013;\n1 : 00008067;\nEND;\n");
+    }
+
+    #[test]
+    fn empty_images_hold_one_word() {
+        let image = image("");
+        assert_eq!(text(coe(&image)), "memory_initialization_radix=16;\nmemory_initialization_vector=\n00000000;\n");
+        assert!(text(mif(&image)).starts_with("DEPTH = 1;\n"));
+        assert!(text(mif(&image)).contains("\n0 : 00000000;\nEND;\n"));
+    }
+}
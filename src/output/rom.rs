@@ -0,0 +1,213 @@
+use super::{Format, WriteOptions};
+use super::mem;
+use crate::linker::Image;
+use crate::utils::exception::AsmRiscVError;
+
+/// Bits a ROM word can hold
+pub const WORD_SIZES: [u32; 4] = [8, 16, 32, 64];
+
+/// Widest address bus of a ROM in `format`. VHDL converts the address to an
+/// `integer`, which only holds 31 bits.
+pub fn max_address_width(format: Format) -> u32 {
+    match format {
+        Format::Vhdl => 31,
+        _ => 32
+    }
+}
+
+/// How the ROM contents are described to synthesis
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum RomStyle {
+    /// One `case` branch per word
+    #[default]
+    Case,
+    /// A constant array indexed by the address
+    Array,
+}
+
+impl RomStyle {
+    pub fn from_name(name: &str) -> Option<RomStyle> {
+        match name {
+            "case" => Some(RomStyle::Case),
+            "array" => Some(RomStyle::Array),
+            _ => None
+        }
+    }
+}
+
+/// Contents and shape of the generated ROM
+struct Rom<'a> {
+    name: &'a str,
+    style: RomStyle,
+    address_width: u32,
+    word_size: u32,
+    words: Vec<u64>,
+}
+
+impl<'a> Rom<'a> {
+    /// The command line only lets through word sizes and widths `format` supports
+    fn new(image: &Image, options: &'a WriteOptions, format: Format) -> Result<Rom<'a>, AsmRiscVError> {
+        let word_size = options.word_size.unwrap_or(32);
+        if !WORD_SIZES.contains(&word_size) {
+            return Err(AsmRiscVError::Internal("unsupported ROM word size"));
+        }
+
+        let mut words = mem::words(image, (word_size / 8) as usize);
+        if words.is_empty() {
+            words.push(0);
+        }
+
+        // Narrowest address bus that reaches every word
+        let needed = (usize::BITS - (words.len() - 1).leading_zeros()).max(1);
+        let address_width = options.address_width.unwrap_or(needed);
+        let max = max_address_width(format);
+        if address_width < needed || address_width > max {
+            return Err(AsmRiscVError::ValueOutOfRange { value: address_width as i64, min: needed as i64, max: max as i64, context: "address width" });
+        }
+
+        Ok(Rom { name: options.rom_name.as_deref().unwrap_or("rom"), style: options.rom_style, address_width, word_size, words })
+    }
+
+    fn literal(&self, word: u64) -> String {
+        format!("{:0width$x}", word, width = (self.word_size / 4) as usize)
+    }
+}
+
+/// A SystemVerilog module with a registered read port
+pub fn system_verilog(image: &Image, options: &WriteOptions) -> Result<Vec<u8>, AsmRiscVError> {
+    let rom = Rom::new(image, options, Format::SystemVerilog)?;
+    let mut text = format!("// {} words of {} bits\n", rom.words.len(), rom.word_size);
+    text.push_str(&format!("module {} #(\n", rom.name));
+    text.push_str(&format!("    parameter int ADDR_WIDTH = {},\n", rom.address_width));
+    text.push_str(&format!("    parameter int DATA_WIDTH = {}\n", rom.word_size));
+    text.push_str(") (\n");
+    text.push_str("    input  logic                  clk,\n");
+    text.push_str("    input  logic [ADDR_WIDTH-1:0] addr,\n");
+    text.push_str("    output logic [DATA_WIDTH-1:0] data\n");
+    text.push_str(");\n");
+
+    match rom.style {
+        RomStyle::Case => {
+            text.push_str("    always_ff @(posedge clk) begin\n");
+            text.push_str("        case (addr)\n");
+            for (address, word) in rom.words.iter().enumerate() {
+                text.push_str(&format!("            {}: data <= DATA_WIDTH'({}'h{});\n", address, rom.word_size, rom.literal(*word)));
+            }
+            text.push_str("            default: data <= '0;\n");
+            text.push_str("        endcase\n");
+            text.push_str("    end\n");
+        },
+        RomStyle::Array => {
+            let words: Vec<String> = rom.words.iter()
+                                              .map(|word| format!("        DATA_WIDTH'({}'h{})", rom.word_size, rom.literal(*word)))
+                                              .collect();
+            text.push_str(&format!("    localparam int DEPTH = {};\n", rom.words.len()));
+            text.push_str("    localparam logic [DATA_WIDTH-1:0] CONTENTS [DEPTH] = '{\n");
+            text.push_str(&words.join(",\n"));
+            text.push_str("\n    };\n\n");
+            text.push_str("    always_ff @(posedge clk) begin\n");
+            text.push_str("        data <= (addr < DEPTH) ? CONTENTS[addr] : '0;\n");
+            text.push_str("    end\n");
+        },
+    }
+
+    text.push_str("endmodule\n");
+    Ok(text.into_bytes())
+}
+
+/// A VHDL entity with a registered read port
+pub fn vhdl(image: &Image, options: &WriteOptions) -> Result<Vec<u8>, AsmRiscVError> {
+    let rom = Rom::new(image, options, Format::Vhdl)?;
+    let value = |word: u64| format!("std_logic_vector(resize(unsigned'(x\"{}\"), DATA_WIDTH))", rom.literal(word));
+
+    let mut text = format!("-- {} words of {} bits\n", rom.words.len(), rom.word_size);
+    text.push_str("library ieee;\n");
+    text.push_str("use ieee.std_logic_1164.all;\n");
+    text.push_str("use ieee.numeric_std.all;\n\n");
+    text.push_str(&format!("entity {} is\n", rom.name));
+    text.push_str("    generic (\n");
+    text.push_str(&format!("        ADDR_WIDTH : natural := {};\n", rom.address_width));
+    text.push_str(&format!("        DATA_WIDTH : natural := {}\n", rom.word_size));
+    text.push_str("    );\n");
+    text.push_str("    port (\n");
+    text.push_str("        clk  : in  std_logic;\n");
+    text.push_str("        addr : in  std_logic_vector(ADDR_WIDTH - 1 downto 0);\n");
+    text.push_str("        data : out std_logic_vector(DATA_WIDTH - 1 downto 0)\n");
+    text.push_str("    );\n");
+    text.push_str(&format!("end entity {};\n\n", rom.name));
+    text.push_str(&format!("architecture rtl of {} is\n", rom.name));
+
+    match rom.style {
+        RomStyle::Case => {
+            text.push_str("begin\n");
+            text.push_str("    process (clk)\n");
+            text.push_str("    begin\n");
+            text.push_str("        if rising_edge(clk) then\n");
+            text.push_str("            case to_integer(unsigned(addr)) is\n");
+            for (address, word) in rom.words.iter().enumerate() {
+                text.push_str(&format!("                when {} => data <= {};\n", address, value(*word)));
+            }
+            text.push_str("                when others => data <= (others => '0');\n");
+            text.push_str("            end case;\n");
+            text.push_str("        end if;\n");
+            text.push_str("    end process;\n");
+        },
+        RomStyle::Array => {
+            let words: Vec<String> = rom.words.iter()
+                                              .enumerate()
+                                              .map(|(address, word)| format!("        {} => {}", address, value(*word)))
+                                              .collect();
+            text.push_str(&format!("    constant DEPTH : natural := {};\n", rom.words.len()));
+            text.push_str("    type rom_type is array (0 to DEPTH - 1) of std_logic_vector(DATA_WIDTH - 1 downto 0);\n");
+            text.push_str("    constant CONTENTS : rom_type := (\n");
+            text.push_str(&words.join(",\n"));
+            text.push_str("\n    );\n");
+            text.push_str("begin\n");
+            text.push_str("    process (clk)\n");
+            text.push_str("    begin\n");
+            text.push_str("        if rising_edge(clk) then\n");
+            text.push_str("            if to_integer(unsigned(addr)) < DEPTH then\n");
+            text.push_str("                data <= CONTENTS(to_integer(unsigned(addr)));\n");
+            text.push_str("            else\n");
+            text.push_str("                data <= (others => '0');\n");
+            text.push_str("            end if;\n");
+            text.push_str("        end if;\n");
+            text.push_str("    end process;\n");
+        },
+    }
+
+    text.push_str("end architecture rtl;\n");
+    Ok(text.into_bytes())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{system_verilog, vhdl};
+    use crate::assembler;
+    use crate::linker::{self, Image, LinkOptions};
+    use crate::output::WriteOptions;
+    use crate::utils::exception::AsmRiscVError;
+
+    fn image() -> Image {
+        let (object, diagnostics) = assembler::assemble("nop\nret");
+        assert!(diagnostics.is_empty(), "{:?}", diagnostics);
+        linker::link(&[object], &LinkOptions::default()).unwrap()
+    }
+
+    #[test]
+    fn vhdl_addresses_fit_an_integer() {
+        let image = image();
+        let options = WriteOptions { address_width: Some(32), ..WriteOptions::default() };
+        assert!(system_verilog(&image, &options).is_ok());
+        assert!(matches!(vhdl(&image, &options), Err(AsmRiscVError::ValueOutOfRange { value: 32, max: 31, .. })));
+
+        let options = WriteOptions { address_width: Some(31), ..WriteOptions::default() };
+        assert!(vhdl(&image, &options).is_ok());
+    }
+
+    #[test]
+    fn rejects_unsupported_word_sizes() {
+        let options = WriteOptions { word_size: Some(12), ..WriteOptions::default() };
+        assert!(matches!(vhdl(&image(), &options), Err(AsmRiscVError::Internal(_))));
+    }
+}
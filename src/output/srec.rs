@@ -0,0 +1,57 @@
+use crate::linker::Image;
+
+/// Data bytes per record
+const RECORD_LEN: usize = 16;
+
+/// Serialize a linked program as Motorola S-records.
+/// The address width is the smallest of 16, 24 and 32 bits that fits every
+/// address, and selects S1/S9, S2/S8 or S3/S7 records.
+pub fn write(image: &Image) -> Vec<u8> {
+    let end = image.loaded()
+                   .map(|output| output.address as u64 + output.bytes.len() as u64 - 1)
+                   .chain(image.entry.map(|entry| entry as u64))
+                   .max()
+                   .unwrap_or(0);
+    let (data, start, width) = match end {
+        0..=0xffff => (1, 9, 2),
+        0x10000..=0xffffff => (2, 8, 3),
+        _ => (3, 7, 4),
+    };
+
+    let mut srec = String::new();
+    record(&mut srec, 0, 0, 2, &[]);
+
+    let mut count = 0_u32;
+    for output in image.loaded() {
+        for (i, chunk) in output.bytes.chunks(RECORD_LEN).enumerate() {
+            record(&mut srec, data, output.address + (i * RECORD_LEN) as u32, width, chunk);
+            count += 1;
+        }
+    }
+
+    // S5 holds a 16-bit record count, S6 a 24-bit one
+    match count {
+        0..=0xffff => record(&mut srec, 5, count, 2, &[]),
+        0x10000..=0xffffff => record(&mut srec, 6, count, 3, &[]),
+        _ => {}
+    }
+    record(&mut srec, start, image.entry.unwrap_or(0), width, &[]);
+
+    srec.into_bytes()
+}
+
+/// `SnLL<address><data>CC` where `LL` counts the bytes after itself and `CC`
+/// is the ones' complement of their sum
+fn record(srec: &mut String, kind: u8, address: u32, width: usize, data: &[u8]) {
+    let mut bytes = vec![(width + data.len() + 1) as u8];
+    bytes.extend_from_slice(&address.to_be_bytes()[4 - width..]);
+    bytes.extend_from_slice(data);
+    let checksum = !bytes.iter().fold(0_u8, |sum, byte| sum.wrapping_add(*byte));
+    bytes.push(checksum);
+
+    srec.push_str(&format!("S{}", kind));
+    for byte in bytes {
+        srec.push_str(&format!("{:02X}", byte));
+    }
+    srec.push('\n');
+}
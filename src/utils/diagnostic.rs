@@ -0,0 +1,120 @@
+use super::exception::AsmRiscVError;
+
+use std::fmt;
+use std::ops::Range;
+
+/// An error about part of a statement
+#[derive(Debug)]
+pub struct Spanned {
+    pub error: AsmRiscVError,
+    /// Bytes of the statement the error is about, or `None` for all of it
+    pub span: Option<Range<usize>>,
+}
+
+impl From<AsmRiscVError> for Spanned {
+    fn from(error: AsmRiscVError) -> Self {
+        Spanned { error, span: None }
+    }
+}
+
+/// Whether a diagnostic stops the file from being assembled
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Severity::Error => write!(f, "error"),
+            Severity::Warning => write!(f, "warning"),
+        }
+    }
+}
+
+/// An error or warning located in a source file
+#[derive(Debug)]
+pub struct Diagnostic {
+    pub error: AsmRiscVError,
+    pub severity: Severity,
+    /// 1-based line the error is on
+    pub line: usize,
+    /// Bytes of that line to underline
+    pub columns: Range<usize>,
+}
+
+impl Diagnostic {
+    /// Place an error about the `len` bytes long statement found `column` bytes into `line`
+    pub fn new(error: impl Into<Spanned>, line: usize, column: usize, len: usize) -> Diagnostic {
+        let Spanned { error, span } = error.into();
+        let span = span.unwrap_or(0..len);
+        Diagnostic { error, severity: Severity::Error, line, columns: column + span.start..column + span.end }
+    }
+
+    /// Place an error about the first mention of `token` on `line` of `source`, ignoring case,
+    /// or about the whole line when it does not mention it
+    pub fn find(error: AsmRiscVError, source: &str, line: usize, token: &str) -> Diagnostic {
+        let text = source.lines().nth(line.saturating_sub(1)).unwrap_or("").to_ascii_lowercase();
+        let symbol_char = |c: char| c.is_ascii_alphanumeric() || "_.$".contains(c);
+        let whole_word = |&(start, _): &(usize, &str)| {
+            !text[..start].ends_with(symbol_char) && !text[start + token.len()..].starts_with(symbol_char)
+        };
+        let columns = match text.match_indices(&token.to_ascii_lowercase()).find(whole_word).map(|(start, _)| start) {
+            Some(start) => start..start + token.len(),
+            None => {
+                let start = text.len() - text.trim_start().len();
+                start..text.trim_end().len().max(start)
+            }
+        };
+        Diagnostic { error, severity: Severity::Error, line, columns }
+    }
+
+    pub fn warning(self) -> Diagnostic {
+        Diagnostic { severity: Severity::Warning, ..self }
+    }
+
+    pub fn is_error(&self) -> bool {
+        self.severity == Severity::Error
+    }
+
+    /// Render the way rustc does: the message, `file:line:column`, then the source line
+    /// with carets under the offending text
+    pub fn render(&self, file: &str, source: &str) -> String {
+        let text = source.lines().nth(self.line.saturating_sub(1)).unwrap_or("");
+        let start = floor_char_boundary(text, self.columns.start);
+        let end = floor_char_boundary(text, self.columns.end).max(start);
+        let column = text[..start].chars().count() + 1;
+
+        // Keep tabs so the carets line up however the terminal expands them
+        let padding: String = text[..start].chars().map(|c| if c == '\t' { '\t' } else { ' ' }).collect();
+        let carets = "^".repeat(text[start..end].chars().count().max(1));
+
+        let gutter = " ".repeat(self.line.to_string().len());
+        format!("{}[{}]: {}\n{}--> {}:{}:{}\n{} |\n{} | {}\n{} | {}{}\n",
+                self.severity, self.error.code(), self.error, gutter, file, self.line, column, gutter, self.line, text, gutter, padding, carets)
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}: {}[{}]: {}", self.line, self.severity, self.error.code(), self.error)
+    }
+}
+
+/// Largest char boundary of `text` at or before `index`
+fn floor_char_boundary(text: &str, index: usize) -> usize {
+    (0..=index.min(text.len())).rev().find(|i| text.is_char_boundary(*i)).unwrap_or(0)
+}
+
+/// Closing line after every diagnostic has been printed, like rustc's
+/// `aborting due to 2 previous errors; 1 warning emitted`
+pub fn summary(errors: usize, warnings: usize) -> Option<String> {
+    let plural = |count: usize, noun: &str| format!("{} {}{}", count, noun, if count == 1 { "" } else { "s" });
+    match (errors, warnings) {
+        (0, 0) => None,
+        (0, warnings) => Some(format!("warning: {} emitted", plural(warnings, "warning"))),
+        (errors, 0) => Some(format!("error: aborting due to {}", plural(errors, "previous error"))),
+        (errors, warnings) => Some(format!("error: aborting due to {}; {} emitted", plural(errors, "previous error"), plural(warnings, "warning"))),
+    }
+}
@@ -2,24 +2,154 @@ use thiserror::Error;
 
 #[derive(Error, Debug)]
 pub enum AsmRiscVError {
-    #[error("")]
-    NotImplementedInstruction,
+    #[error("unknown instruction `{0}`")]
+    UnknownInstruction(String),
 
-    #[error("")]
-    NotExistRegister,
+    #[error("unknown directive `{0}`")]
+    UnknownDirective(String),
 
-    #[error("")]
-    SyntaxError,
+    #[error("unknown section `{0}`, expected `.text`, `.rodata`, `.data`, `.bss` or a subsection of one")]
+    UnknownSection(String),
 
-    #[error("")]
+    #[error("wrong number of operands, expected `{}`", format!("{} {}", .mnemonic, .expected).trim_end())]
+    OperandCount { mnemonic: String, expected: &'static str },
+
+    #[error("expected {expected}, found `{found}`")]
+    UnexpectedToken { found: String, expected: &'static str },
+
+    #[error("expected {expected}, found nothing")]
+    MissingOperand { expected: &'static str },
+
+    #[error("no such register `{0}`, numeric names go from x0 to x31")]
+    NoSuchRegister(String),
+
+    #[error("invalid symbol name `{0}`, expected letters, digits, `_`, `.` or `$` not starting with a digit")]
+    InvalidSymbolName(String),
+
+    #[error("invalid number `{0}`, expected decimal digits or a `0x`, `0b` or `0o` prefixed literal that fits in 64 bits")]
+    InvalidNumber(String),
+
+    #[error("invalid character literal `{0}`, expected one character or escape such as `'a'` or `'\\n'`")]
+    InvalidCharacter(String),
+
+    #[error("invalid escape sequence `{0}` in string")]
+    InvalidEscape(String),
+
+    #[error("unknown relocation operator `{0}`, expected `%hi`, `%lo`, `%pcrel_hi` or `%pcrel_lo`")]
+    UnknownModifier(String),
+
+    #[error("`{modifier}` is not allowed in the {context}")]
+    MisplacedModifier { modifier: &'static str, context: &'static str },
+
+    #[error("`%pcrel_lo` refers to address {0:#x}, which holds no `%pcrel_hi`")]
+    UnmatchedPcrelLo(u32),
+
+    #[error("expected a constant, found an address in `{0}`")]
+    NotAbsolute(&'static str),
+
+    #[error("`{0}` is only defined for an address and a constant, or for two addresses in the same section")]
+    AddressArithmetic(&'static str),
+
+    #[error("division by zero")]
+    DivisionByZero,
+
+    #[error("{value} is out of range for the {format} ({min} to {max})")]
+    ImmediateOutOfRange { value: i64, min: i64, max: i64, format: &'static str },
+
+    #[error("offset {offset} is odd, the {format} needs a multiple of 2")]
+    MisalignedOffset { offset: i64, format: &'static str },
+
+    #[error("{context} {value} is out of range ({min} to {max})")]
+    ValueOutOfRange { value: i64, min: i64, max: i64, context: &'static str },
+
+    #[error("alignment {0} is not a power of two")]
+    NotPowerOfTwo(i64),
+
+    #[error("addresses only fit in 4-byte `.word` data, found a {0}-byte value")]
+    AddressWidth(usize),
+
+    #[error("`.org` can not move backwards from offset {from} to {to}")]
+    OrgBackwards { from: u32, to: i64 },
+
+    #[error("`.org` must stay in `{expected}`, found an address in `{found}`")]
+    OrgSection { expected: &'static str, found: &'static str },
+
+    #[error("{what} at offset {offset:#x} of `{section}` is not {alignment}-byte aligned")]
+    Misaligned { what: &'static str, section: &'static str, offset: u32, alignment: u32 },
+
+    #[error("`.bss` can only hold zeros")]
+    DataInBss,
+
+    #[error("instructions can not be placed in `.bss`")]
+    InstructionInBss,
+
+    #[error("addresses pass the end of the 32-bit address space")]
+    AddressOverflow,
+
+    #[error("empty statement")]
     ParseEmptyLine,
 
-    #[error("")]
-    ImmediateOverflow,
+    #[error("symbol `{0}` is defined more than once")]
+    DuplicateSymbol(String),
+
+    #[error("undefined symbol `{0}`")]
+    UndefinedSymbol(String),
+
+    #[error("illegal instruction {0:#010x}")]
+    IllegalInstruction(u32),
+
+    #[error("not a valid little-endian ELF32 file")]
+    InvalidElf,
+
+    #[error("internal error: {0}")]
+    Internal(&'static str),
 
-    #[error("")]
-    ParseFunctError,
+    #[error("{0} bytes do not fit in the assembler's memory")]
+    OutOfMemory(u32),
+}
 
-    #[error("")]
-    UsedLabel,
-}
\ No newline at end of file
+impl AsmRiscVError {
+    /// Stable identifier of the kind of error, explained by `--explain`.
+    /// Codes are never reused once assigned.
+    pub fn code(&self) -> &'static str {
+        match self {
+            // Only tells the passes a line holds nothing to assemble, so it is never reported
+            // and `--explain` does not list it
+            AsmRiscVError::ParseEmptyLine => "E0000",
+            AsmRiscVError::UnknownInstruction(_) => "E0001",
+            AsmRiscVError::UnknownDirective(_) => "E0002",
+            AsmRiscVError::UnknownSection(_) => "E0003",
+            AsmRiscVError::OperandCount { .. } => "E0004",
+            AsmRiscVError::UnexpectedToken { .. } => "E0005",
+            AsmRiscVError::MissingOperand { .. } => "E0006",
+            AsmRiscVError::NoSuchRegister(_) => "E0007",
+            AsmRiscVError::InvalidSymbolName(_) => "E0008",
+            AsmRiscVError::InvalidNumber(_) => "E0009",
+            AsmRiscVError::InvalidCharacter(_) => "E0010",
+            AsmRiscVError::InvalidEscape(_) => "E0011",
+            AsmRiscVError::ImmediateOutOfRange { .. } => "E0012",
+            AsmRiscVError::UnknownModifier(_) => "E0013",
+            AsmRiscVError::MisplacedModifier { .. } => "E0014",
+            AsmRiscVError::UnmatchedPcrelLo(_) => "E0015",
+            AsmRiscVError::NotAbsolute(_) => "E0016",
+            AsmRiscVError::AddressArithmetic(_) => "E0017",
+            AsmRiscVError::DivisionByZero => "E0018",
+            AsmRiscVError::MisalignedOffset { .. } => "E0019",
+            AsmRiscVError::ValueOutOfRange { .. } => "E0020",
+            AsmRiscVError::NotPowerOfTwo(_) => "E0021",
+            AsmRiscVError::AddressWidth(_) => "E0022",
+            AsmRiscVError::OrgBackwards { .. } => "E0023",
+            AsmRiscVError::OrgSection { .. } => "E0024",
+            AsmRiscVError::DataInBss => "E0025",
+            AsmRiscVError::InstructionInBss => "E0026",
+            AsmRiscVError::AddressOverflow => "E0027",
+            AsmRiscVError::DuplicateSymbol(_) => "E0028",
+            AsmRiscVError::UndefinedSymbol(_) => "E0029",
+            AsmRiscVError::IllegalInstruction(_) => "E0030",
+            AsmRiscVError::InvalidElf => "E0031",
+            AsmRiscVError::Internal(_) => "E0032",
+            AsmRiscVError::OutOfMemory(_) => "E0033",
+            AsmRiscVError::Misaligned { .. } => "W0001",
+        }
+    }
+}
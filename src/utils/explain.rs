@@ -0,0 +1,631 @@
+/// Long-form description of the error with `code`, with an incorrect and a corrected example
+pub fn explain(code: &str) -> Option<&'static str> {
+    let text = match code.to_ascii_uppercase().as_str() {
+        "E0001" => r#"E0001: unknown instruction
+
+The mnemonic is neither an RV32I instruction nor one of the supported
+pseudo-instructions (`nop`, `li`, `mv`, `not`, `neg`, `seqz`, `snez`, `j`,
+`jr`, `ret`, `call`, `tail`, `beqz`, `bnez`, `bgt`, `ble`, `bgtu`, `bleu`).
+Extensions such as M (`mul`, `div`) are not supported.
+
+Erroneous code example:
+
+    mul a0, a1, a2
+
+Corrected, using only RV32I:
+
+    mv a0, zero
+    beqz a2, done
+    loop:
+    add a0, a0, a1
+    addi a2, a2, -1
+    bnez a2, loop
+    done:
+"#,
+
+        "E0002" => r#"E0002: unknown directive
+
+A statement starting with `.` names a directive the assembler does not know.
+The supported directives are `.text`, `.data`, `.rodata`, `.bss`, `.section`,
+`.byte`, `.half`, `.word`, `.dword`, `.ascii`, `.asciz`, `.string`, `.space`,
+`.zero`, `.org`, `.align`, `.p2align`, `.balign`, `.fill`, `.equ`, `.set`,
+`.globl` and `.global`.
+
+Erroneous code example:
+
+    .long 42
+
+Corrected:
+
+    .word 42
+"#,
+
+        "E0003" => r#"E0003: unknown section
+
+`.section` only accepts `.text`, `.rodata`, `.data` and `.bss`, their
+small-data variants `.srodata`, `.sdata` and `.sbss`, and subsections of them
+such as `.text.startup`.
+
+Erroneous code example:
+
+    .section .vectors
+
+Corrected:
+
+    .section .text.vectors
+"#,
+
+        "E0004" => r#"E0004: wrong number of operands
+
+An instruction was given more operands than it takes, or a pseudo-instruction
+or directive more or fewer. The message shows the expected form. A real
+instruction missing its last operands reports E0006 instead.
+
+Erroneous code example:
+
+    add a0, a0, a1, a2
+
+Corrected:
+
+    add a0, a0, a1
+"#,
+
+        "E0005" => r#"E0005: unexpected token
+
+An operand is not of the expected kind, for example a number where a register
+is expected or a stray character inside an expression.
+
+Erroneous code example:
+
+    addi a0, 5, a1
+
+Corrected:
+
+    addi a0, a1, 5
+"#,
+
+        "E0006" => r#"E0006: missing operand
+
+An operand or part of one is missing: a trailing comma, an empty memory
+operand base, or an expression that ends after an operator.
+
+Erroneous code example:
+
+    lw a0, 8()
+    li a1, 4 +
+
+Corrected:
+
+    lw a0, 8(sp)
+    li a1, 4 + 1
+"#,
+
+        "E0007" => r#"E0007: no such register
+
+A numeric register name is past the end of the register file. The integer
+registers are `x0` to `x31`, also known by their ABI names `zero`, `ra`, `sp`,
+`gp`, `tp`, `t0`-`t6`, `s0`/`fp`, `s1`-`s11` and `a0`-`a7`.
+
+Erroneous code example:
+
+    addi x32, x0, 1
+
+Corrected:
+
+    addi x31, x0, 1
+"#,
+
+        "E0008" => r#"E0008: invalid symbol name
+
+Labels and `.equ`/`.set` names are made of letters, digits, `_`, `.` and `$`,
+and must not start with a digit.
+
+Erroneous code example:
+
+    2nd_loop:
+        addi a0, a0, -1
+
+Corrected:
+
+    second_loop:
+        addi a0, a0, -1
+"#,
+
+        "E0009" => r#"E0009: invalid number
+
+A numeric literal contains digits its base does not allow, has an unknown
+prefix, or does not fit in 64 bits. Decimal literals are written as plain
+digits, others with a `0x`, `0b` or `0o` prefix.
+
+Erroneous code example:
+
+    li a0, 0x1g
+    li a1, 0b102
+
+Corrected:
+
+    li a0, 0x1f
+    li a1, 0b101
+"#,
+
+        "E0010" => r#"E0010: invalid character literal
+
+A character literal holds exactly one character or one of the escapes `\n`,
+`\t`, `\r`, `\0`, `\\`, `\'` and `\"` between single quotes.
+
+Erroneous code example:
+
+    li a0, 'ab'
+
+Corrected:
+
+    li a0, 'a'
+"#,
+
+        "E0011" => r#"E0011: invalid escape sequence
+
+A string literal contains a backslash followed by something that is not a
+C escape. Supported are `\n`, `\t`, `\r`, `\a`, `\b`, `\f`, `\v`, `\e`, `\\`,
+`\"`, `\'`, octal `\NNN` and hexadecimal `\xNN`.
+
+Erroneous code example:
+
+    .asciz "C:\dos\run"
+
+Corrected:
+
+    .asciz "C:\\dos\\run"
+"#,
+
+        "E0012" => r#"E0012: immediate out of range
+
+An immediate operand does not fit the field of its instruction format. I-type
+and S-type immediates take -2048 to 2047, shift amounts 0 to 31 and U-type
+immediates 20 bits. Larger constants have to be built in a register first,
+which `li` does for you.
+
+Erroneous code example:
+
+    addi a0, a0, 5000
+
+Corrected:
+
+    li t0, 5000
+    add a0, a0, t0
+"#,
+
+        "E0013" => r#"E0013: unknown relocation operator
+
+Only `%hi`, `%lo`, `%pcrel_hi` and `%pcrel_lo` are supported in front of a
+parenthesised expression.
+
+Erroneous code example:
+
+    lui a0, %high(buffer)
+    .data
+    buffer: .space 16
+
+Corrected:
+
+    lui a0, %hi(buffer)
+    .data
+    buffer: .space 16
+"#,
+
+        "E0014" => r#"E0014: relocation operator not allowed here
+
+`%hi` and `%pcrel_hi` produce the upper 20 bits of an address and belong in
+`lui` and `auipc`. `%lo` and `%pcrel_lo` produce the lower 12 bits and belong
+in I-type and S-type immediates such as `addi`, loads and stores. None of them
+can be used as a shift amount or as a data value.
+
+Erroneous code example:
+
+    lui a0, %lo(buffer)
+    addi a0, a0, %hi(buffer)
+    .data
+    buffer: .space 16
+
+Corrected:
+
+    lui a0, %hi(buffer)
+    addi a0, a0, %lo(buffer)
+    .data
+    buffer: .space 16
+"#,
+
+        "E0015" => r#"E0015: `%pcrel_lo` without a matching `%pcrel_hi`
+
+The operand of `%pcrel_lo` is the label of the `auipc` that holds the matching
+`%pcrel_hi`, not the symbol being addressed.
+
+Erroneous code example:
+
+    .Lhi: auipc a0, %pcrel_hi(buffer)
+          addi a0, a0, %pcrel_lo(buffer)
+    .data
+    buffer: .space 16
+
+Corrected:
+
+    .Lhi: auipc a0, %pcrel_hi(buffer)
+          addi a0, a0, %pcrel_lo(.Lhi)
+    .data
+    buffer: .space 16
+"#,
+
+        "E0016" => r#"E0016: address used where a constant is required
+
+Sizes, counts, alignments, shift amounts and `.equ` values must be plain
+numbers, but the expression evaluates to the address of a label. The
+difference of two labels in the same section is a plain number.
+
+Erroneous code example:
+
+    .data
+    table: .word 1, 2, 3
+    .equ TABLE_SIZE, table
+
+Corrected:
+
+    .data
+    table: .word 1, 2, 3
+    table_end:
+    .equ TABLE_SIZE, table_end - table
+"#,
+
+        "E0017" => r#"E0017: invalid arithmetic on addresses
+
+Addresses are only known relative to the start of their section, so the only
+operations allowed on them are adding or subtracting a constant and
+subtracting two addresses in the same section.
+
+Erroneous code example:
+
+    message: .asciz "hello"
+    message_end:
+    .equ TWICE, message * 2
+
+Corrected:
+
+    message: .asciz "hello"
+    message_end:
+    .equ LENGTH, message_end - message
+"#,
+
+        "E0018" => r#"E0018: division by zero
+
+A `/` or `%` in a constant expression has a divisor that evaluates to zero.
+
+Erroneous code example:
+
+    .equ WORDS, 0
+    li a0, 64 / WORDS
+
+Corrected:
+
+    .equ WORDS, 4
+    li a0, 64 / WORDS
+"#,
+
+        "E0019" => r#"E0019: odd branch or jump offset
+
+Branch and jump offsets are multiples of 2 because their lowest bit is not
+encoded. A numeric target is the offset from the branch itself, so it must be
+even; a label always is.
+
+Erroneous code example:
+
+    beq a0, a1, 7
+
+Corrected:
+
+    beq a0, a1, 8
+"#,
+
+        "E0020" => r#"E0020: value out of range
+
+A directive operand is outside the values it accepts, for example a `.byte`
+value that does not fit in 8 bits, a negative `.space` size or an alignment
+exponent above 30.
+
+Erroneous code example:
+
+    .byte 300
+
+Corrected:
+
+    .half 300
+"#,
+
+        "E0021" => r#"E0021: alignment is not a power of two
+
+`.balign` takes a byte boundary, which has to be a power of two. `.align` and
+`.p2align` take the exponent instead.
+
+Erroneous code example:
+
+    .balign 12
+
+Corrected:
+
+    .balign 16
+"#,
+
+        "E0022" => r#"E0022: address in narrow data
+
+Addresses are 32 bits wide and are only stored by `.word`. Smaller data
+directives can hold the distance between two labels instead.
+
+Erroneous code example:
+
+    handler: ret
+    .data
+    .half handler
+
+Corrected:
+
+    handler: ret
+    .data
+    .word handler
+"#,
+
+        "E0023" => r#"E0023: `.org` moves backwards
+
+`.org` can only pad forward to an offset at or after the current position in
+the section.
+
+Erroneous code example:
+
+    .word 1, 2, 3
+    .org 4
+
+Corrected:
+
+    .word 1, 2, 3
+    .org 16
+"#,
+
+        "E0024" => r#"E0024: `.org` target in another section
+
+The operand of `.org` is an offset in the current section, so it can not be a
+label from a different section.
+
+Erroneous code example:
+
+    .text
+    main: ret
+    .data
+    .org main + 16
+
+Corrected:
+
+    .text
+    main: ret
+    .data
+    .org 16
+"#,
+
+        "E0025" => r#"E0025: non-zero data in `.bss`
+
+`.bss` is zero-filled when the program starts and is not stored in the output
+file, so it can only reserve space. Initialised data belongs in `.data`.
+
+Erroneous code example:
+
+    .bss
+    counter: .word 5
+
+Corrected:
+
+    .data
+    counter: .word 5
+"#,
+
+        "E0026" => r#"E0026: instruction in `.bss`
+
+`.bss` only reserves zero-filled space and can not hold code.
+
+Erroneous code example:
+
+    .bss
+    buffer: .space 64
+    ret
+
+Corrected:
+
+    .bss
+    buffer: .space 64
+    .text
+    ret
+"#,
+
+        "E0027" => r#"E0027: address overflow
+
+The program, or one of its sections placed at the chosen base address, runs
+past the end of the 32-bit address space.
+
+Erroneous code example (with `--base 0xfffff000`):
+
+    .space 0x2000
+
+Corrected (with `--base 0x80000000`):
+
+    .space 0x2000
+"#,
+
+        "E0028" => r#"E0028: symbol defined more than once
+
+A label or constant name is defined twice in the same file, or a `.globl`
+symbol is defined by more than one file. `.set` may redefine a constant;
+`.equ` and labels may not.
+
+Erroneous code example:
+
+    loop: addi a0, a0, -1
+    loop: bnez a0, loop
+
+Corrected:
+
+    loop: addi a0, a0, -1
+          bnez a0, loop
+"#,
+
+        "E0029" => r#"E0029: undefined symbol
+
+A symbol is used but never defined. A symbol defined in another file must be
+declared `.globl` there and that file must be assembled together with this one.
+
+Erroneous code example:
+
+    call print_number
+
+Corrected:
+
+    call print_number
+    ret
+
+    print_number:
+        li a7, 1
+        ecall
+        ret
+"#,
+
+        "E0030" => r#"E0030: illegal instruction
+
+A word being decoded is not the encoding of any supported instruction, for
+example data placed in `.text` or an instruction from an unsupported
+extension. `disasm` shows such words as `.word` instead of failing.
+
+Erroneous code example, which disassembles as two `.word`s:
+
+    .word 0x00000000
+    .word 0xffffffff
+
+Corrected, which disassembles as `nop`:
+
+    .word 0x00000013
+"#,
+
+        "E0031" => r#"E0031: invalid ELF file
+
+The input file starts like an ELF file but is not a little-endian 32-bit one,
+or its headers point outside the file. Objects built for RV64 are ELF64.
+
+Erroneous example:
+
+    riscv64-unknown-elf-as -o program.o program.s
+    cargo run disasm program.o
+
+Corrected:
+
+    riscv64-unknown-elf-as -march=rv32i -mabi=ilp32 -o program.o program.s
+    cargo run disasm program.o
+"#,
+
+        "E0032" => r#"E0032: internal error
+
+The assembler reached a state it should never be in, for example a statement
+changing size between the layout pass and the encoding pass. This is a bug in
+the assembler rather than in the program being assembled.
+
+Please report it together with the smallest source file that triggers it.
+"#,
+
+        "E0033" => r#"E0033: out of memory
+
+A `.space`, `.fill`, `.org` or `.align` asks for more bytes than the assembler
+can allocate. The program fits the 32-bit address space, but the whole image
+is held in memory while it is assembled and linked.
+
+Erroneous code example:
+
+    .space 0xffff0000
+
+Corrected, sized to what the program uses:
+
+    .space 0x10000
+"#,
+
+        "W0001" => r#"W0001: misaligned instruction or data
+
+An instruction does not start on a 4-byte boundary, or `.half`/`.word`/`.dword`
+data does not start on its natural boundary. Cores without misaligned access
+support fault when fetching or loading it. Only the first of a run of
+misaligned statements is reported.
+
+Erroneous code example:
+
+    .byte 1
+    .word 0x12345678
+
+Corrected:
+
+    .byte 1
+    .balign 4
+    .word 0x12345678
+"#,
+
+        _ => return None
+    };
+
+    Some(text)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::explain;
+    use crate::utils::exception::AsmRiscVError;
+
+    use std::collections::HashSet;
+
+    #[test]
+    fn every_reported_code_is_explained_once() {
+        let name = || "x".to_string();
+        let errors = [
+            AsmRiscVError::UnknownInstruction(name()),
+            AsmRiscVError::UnknownDirective(name()),
+            AsmRiscVError::UnknownSection(name()),
+            AsmRiscVError::OperandCount { mnemonic: name(), expected: "" },
+            AsmRiscVError::UnexpectedToken { found: name(), expected: "" },
+            AsmRiscVError::MissingOperand { expected: "" },
+            AsmRiscVError::NoSuchRegister(name()),
+            AsmRiscVError::InvalidSymbolName(name()),
+            AsmRiscVError::InvalidNumber(name()),
+            AsmRiscVError::InvalidCharacter(name()),
+            AsmRiscVError::InvalidEscape(name()),
+            AsmRiscVError::UnknownModifier(name()),
+            AsmRiscVError::MisplacedModifier { modifier: "", context: "" },
+            AsmRiscVError::UnmatchedPcrelLo(0),
+            AsmRiscVError::NotAbsolute(""),
+            AsmRiscVError::AddressArithmetic(""),
+            AsmRiscVError::DivisionByZero,
+            AsmRiscVError::ImmediateOutOfRange { value: 0, min: 0, max: 0, format: "" },
+            AsmRiscVError::MisalignedOffset { offset: 0, format: "" },
+            AsmRiscVError::ValueOutOfRange { value: 0, min: 0, max: 0, context: "" },
+            AsmRiscVError::NotPowerOfTwo(0),
+            AsmRiscVError::AddressWidth(0),
+            AsmRiscVError::OrgBackwards { from: 0, to: 0 },
+            AsmRiscVError::OrgSection { expected: "", found: "" },
+            AsmRiscVError::Misaligned { what: "", section: "", offset: 0, alignment: 0 },
+            AsmRiscVError::DataInBss,
+            AsmRiscVError::InstructionInBss,
+            AsmRiscVError::AddressOverflow,
+            AsmRiscVError::DuplicateSymbol(name()),
+            AsmRiscVError::UndefinedSymbol(name()),
+            AsmRiscVError::IllegalInstruction(0),
+            AsmRiscVError::InvalidElf,
+            AsmRiscVError::Internal(""),
+            AsmRiscVError::OutOfMemory(0),
+        ];
+
+        let mut codes = HashSet::new();
+        for error in &errors {
+            let code = error.code();
+            assert!(codes.insert(code), "{} is used twice", code);
+            let text = explain(code).unwrap_or_else(|| panic!("{} is not explained", code));
+            assert!(text.starts_with(&format!("{}: ", code)), "{}", code);
+        }
+        assert!(explain(AsmRiscVError::ParseEmptyLine.code()).is_none());
+    }
+}
@@ -24,16 +24,44 @@ pub fn read_asm(filename: &str) -> io::Result<String> {
     } 
 }
 
-pub fn write_binary(filename: &str, binary_contents: &[u8]) -> io::Result<()> {
-    let filepath = Path::new(filename);
-    
-    // This function only execute when filename is valid. 
-    // So here unwrap is safe
-    let file = fs::File::create(filepath.file_stem().unwrap())?;
+/// Name of the output file for `filename` when none is given: its stem in the
+/// current directory, with `extension` appended if there is one.
+/// Fails when `filename` ends in no file name, like `..`.
+pub fn default_output(filename: &str, extension: Option<&str>) -> io::Result<String> {
+    let Some(stem) = Path::new(filename).file_stem() else {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "no file name to name the output after"));
+    };
+    let stem = stem.to_string_lossy();
+
+    match extension {
+        Some(ext) => Ok(format!("{}.{}", stem, ext)),
+        None => Ok(stem.to_string())
+    }
+}
+
+pub fn read_binary(filename: &str) -> io::Result<Vec<u8>> {
+    fs::read(filename)
+}
+
+pub fn write_output(filename: &str, contents: &[u8]) -> io::Result<()> {
+    let file = fs::File::create(filename)?;
 
     let mut writer = io::BufWriter::new(file);
 
-    writer.write_all(binary_contents)?;
+    writer.write_all(contents)?;
 
     Ok(())
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::default_output;
+
+    #[test]
+    fn names_outputs_after_the_input() {
+        assert_eq!(default_output("src/prog.s", Some("o")).unwrap(), "prog.o");
+        assert_eq!(default_output("prog.asm", None).unwrap(), "prog");
+        assert!(default_output("..", Some("bin")).is_err());
+        assert!(default_output("/", None).is_err());
+    }
+}
@@ -1,2 +1,4 @@
+pub mod diagnostic;
 pub mod exception;
-pub mod file;
\ No newline at end of file
+pub mod explain;
+pub mod file;